@@ -0,0 +1,70 @@
+use std::io::{stdin, IsTerminal};
+
+use crate::cli::cmd_init::input_password;
+use sab::config::{Config, MASTER_PASSWORD_VAR};
+use sab::error::{Result, SabError};
+
+/// Master password of the encrypted profiles file from the environment or prompted for
+pub fn master_password() -> Result<String> {
+    match std::env::var(MASTER_PASSWORD_VAR) {
+        Ok(password) => Ok(password),
+        Err(_) if !stdin().is_terminal() => Err(SabError::Config(format!(
+            "profiles file is encrypted, set {}",
+            MASTER_PASSWORD_VAR
+        ))),
+        Err(_) => input_password("Master Password"),
+    }
+}
+
+pub fn cmd_config_encrypt(mut cfg: Config) -> Result<()> {
+    if cfg.is_encrypted() {
+        return Err(SabError::State(
+            "profiles file is already encrypted".to_string(),
+        ));
+    }
+
+    let password = match std::env::var(MASTER_PASSWORD_VAR) {
+        Ok(password) => password,
+        Err(_) => new_password()?,
+    };
+
+    cfg.encrypt(&password)?;
+    save(&cfg)?;
+
+    log::info!("profiles file encrypted");
+
+    Ok(())
+}
+
+pub fn cmd_config_decrypt(mut cfg: Config) -> Result<()> {
+    if !cfg.is_encrypted() {
+        return Err(SabError::State(
+            "profiles file is not encrypted".to_string(),
+        ));
+    }
+
+    cfg.decrypt();
+    save(&cfg)?;
+
+    log::info!("profiles file decrypted");
+
+    Ok(())
+}
+
+fn new_password() -> Result<String> {
+    let password = input_password("New Master Password")?;
+    if password.is_empty() {
+        return Err(SabError::Config("empty master password".to_string()));
+    }
+
+    if input_password("Repeat Master Password")? != password {
+        return Err(SabError::Config("master passwords don't match".to_string()));
+    }
+
+    Ok(password)
+}
+
+fn save(cfg: &Config) -> Result<()> {
+    cfg.save()
+        .map_err(|e| e.context("failed to save profiles config"))
+}
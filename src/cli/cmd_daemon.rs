@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::cli::cmd_run::{interrupted_jobs, run_and_record};
+use crate::cli::metrics;
+use crate::cli::output::Output;
+use sab::config::{Config, Job, Jobs};
+use sab::error::{Result, SabError};
+
+use chrono::{DateTime, Local};
+use cron::Schedule;
+
+/// Run the scheduled jobs from the jobs file until interrupted.
+///
+/// Jobs run one at a time, a run that was due while another job was running
+/// starts as soon as it finishes and occurrences missed meanwhile are skipped.
+pub async fn cmd_daemon(
+    cfg: &Config,
+    output: &Output,
+    metrics_file: Option<&Path>,
+    metrics_listen: Option<String>,
+) -> Result<()> {
+    let jobs = Jobs::load().map_err(|e| SabError::Config(format!("failed to load jobs: {}", e)))?;
+
+    let mut scheduled: Vec<(&String, &Job, Schedule)> = vec![];
+    for (name, job) in &jobs.jobs {
+        match &job.schedule {
+            Some(expr) => scheduled.push((name, job, parse_schedule(name, expr)?)),
+            None => log::info!("job {} has no schedule, skipping", name),
+        }
+    }
+
+    if scheduled.is_empty() {
+        return Err(SabError::Config(
+            "no scheduled jobs in the jobs file".to_string(),
+        ));
+    }
+
+    if let Some(addr) = metrics_listen {
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(&addr).await {
+                log::error!("{}", err);
+            }
+        });
+    }
+
+    let interrupted = interrupted_jobs(cfg)?;
+    for (name, job, _) in &scheduled {
+        if interrupted.contains(name) {
+            log::info!("resuming interrupted job {}", name);
+            run_and_record(name, job, cfg, output, metrics_file).await;
+        }
+    }
+
+    let mut next: Vec<Option<DateTime<Local>>> = scheduled
+        .iter()
+        .map(|(_, _, schedule)| schedule.upcoming(Local).next())
+        .collect();
+
+    loop {
+        let Some((idx, at)) = next
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, at)| at.map(|at| (idx, at)))
+            .min_by_key(|(_, at)| *at)
+        else {
+            log::info!("no more scheduled runs");
+            return Ok(());
+        };
+
+        let (name, job, schedule) = &scheduled[idx];
+        log::info!("next run of job {} at {}", name, at.to_rfc3339());
+
+        let wait = (at - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = tokio::signal::ctrl_c() => {
+                log::info!("interrupted, stopping");
+                return Ok(());
+            }
+        }
+
+        run_and_record(name, job, cfg, output, metrics_file).await;
+
+        next[idx] = schedule.after(&Local::now()).next();
+    }
+}
+
+/// Parse a standard 5 field cron expression, or the 6-7 field one with seconds
+fn parse_schedule(job: &str, expr: &str) -> Result<Schedule> {
+    let expr = match expr.split_whitespace().count() {
+        5 => format!("0 {}", expr),
+        _ => expr.to_string(),
+    };
+
+    Schedule::from_str(&expr)
+        .map_err(|e| SabError::Config(format!("invalid schedule of job {}: {}", job, e)))
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use crate::cli::cmd_init::confirm;
+use crate::cli::snapshot::Snapshot;
+use sab::config::{Backup, BackupLock, Config};
+use sab::error::{Result, SabError};
+use sab::s3::S3Client;
+
+pub async fn cmd_delete(
+    cl: S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    yes: bool,
+    wait: bool,
+    cfg: &Config,
+) -> Result<()> {
+    let key = cl.key(name);
+    let _lock = BackupLock::acquire(&cfg.backup_dir(&key), name, wait).await?;
+
+    let snapshots = cfg
+        .snapshots(&key)
+        .map_err(|e| e.context("failed to load backup"))?;
+    let backup = snapshot.select(name, snapshots)?;
+    let backup_file = cfg.backup_file(&backup);
+
+    if backup.done && backup.is_locked() {
+        return Err(SabError::State(format!(
+            "backup {} is locked and cannot be deleted",
+            &backup.name
+        )));
+    }
+
+    if !yes && !confirm(&format!("Delete backup {}?", &backup.name))? {
+        log::info!("aborted");
+        return Ok(());
+    }
+
+    delete_backup(&cl, &backup, backup_file.as_path()).await
+}
+
+/// Delete the backup object, or abort the upload if it's not completed, and its local config
+pub async fn delete_backup(cl: &S3Client<'_>, backup: &Backup, backup_file: &Path) -> Result<()> {
+    if backup.done {
+        cl.delete(backup).await?;
+    } else {
+        log::info!("backup is not completed, aborting upload");
+
+        cl.abort_upload(backup).await?;
+    }
+
+    Backup::remove(backup_file).map_err(|e| e.context("failed to remove backup config"))?;
+
+    log::info!("backup {} deleted", &backup.name);
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::fmt::Display;
+
+use sab::config::Profile;
+use sab::error::{Result, SabError};
+use sab::s3::S3Client;
+
+use bytes::Bytes;
+use chrono::Utc;
+
+/// Check the profile credentials, bucket and permissions under the prefix by
+/// writing, reading and deleting a test object, printing the result of every check.
+///
+/// Returns the first failure. The region, Object Lock and versioning checks are
+/// informational and only warn, as restricted policies often deny them.
+pub async fn cmd_doctor(profile: &Profile) -> Result<()> {
+    let cl = S3Client::new(profile).await?;
+    let mut failure = None;
+
+    let accessible = cl.head_bucket().await.map(|_| "");
+
+    if report("credentials and bucket", accessible, &mut failure).is_none() {
+        println!("{:<24}skipped", "permissions");
+        return Err(failure.unwrap());
+    }
+
+    let region = cl
+        .bucket_region()
+        .await
+        .and_then(|region| match region == profile.region {
+            true => Ok(region),
+            false => Err(SabError::Config(format!(
+                "bucket is in {}, the profile uses {}",
+                region, &profile.region
+            ))),
+        });
+    warn("region", region);
+
+    let key = format!("{}.sab-doctor-{}", &profile.prefix, Utc::now().timestamp());
+    let data = Bytes::from_static(b"sab doctor test object");
+
+    let list = cl.list_uploads().await.map(|_| "");
+    report("list", list, &mut failure);
+
+    let (put, version) = match cl.put_object(&key, data.clone()).await {
+        Ok(version) => (Ok(""), version),
+        Err(err) => (Err(err), None),
+    };
+    let written = report("put", put, &mut failure).is_some();
+
+    let multipart = cl.check_multipart(&key).await.map(|_| "");
+    report("multipart upload", multipart, &mut failure);
+
+    if written {
+        let get = cl
+            .get_object(&key)
+            .await
+            .and_then(|read| match read == data {
+                true => Ok(""),
+                false => Err(SabError::Integrity(
+                    "test object content mismatch".to_string(),
+                )),
+            });
+        report("get", get, &mut failure);
+
+        // Deleting the version leaves nothing behind on versioned buckets,
+        // it needs a permission of its own though
+        let delete = match cl.delete_object(&key, version.as_deref()).await {
+            Err(err) if version.is_some() => {
+                warn::<&str>("delete version", Err(err));
+                cl.delete_object(&key, None)
+                    .await
+                    .map(|_| "test object version left behind")
+            }
+            res => res.map(|_| ""),
+        };
+        report("delete", delete, &mut failure);
+    } else {
+        println!("{:<24}skipped", "get");
+        println!("{:<24}skipped", "delete");
+    }
+
+    let lock = cl
+        .object_lock()
+        .await
+        .map(|lock| lock.unwrap_or_else(|| "not enabled".to_string()));
+    warn("object lock", lock);
+
+    let versioning = cl
+        .versioning()
+        .await
+        .map(|status| status.unwrap_or_else(|| "not enabled".to_string()));
+    warn("versioning", versioning);
+
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+// Print the result of an informational check, a failure is only a warning
+fn warn<T: Display>(check: &str, res: Result<T>) {
+    match res {
+        Ok(val) => println!("{:<24}{}", check, format!("ok {}", val).trim_end()),
+        Err(err) => println!("{:<24}WARNING {}", check, err),
+    }
+}
+
+// Print the check result, keeping the first failure
+fn report<T: Display>(check: &str, res: Result<T>, failure: &mut Option<SabError>) -> Option<T> {
+    match res {
+        Ok(val) => {
+            println!("{:<24}{}", check, format!("ok {}", val).trim_end());
+            Some(val)
+        }
+        Err(err) => {
+            println!("{:<24}FAILED {}", check, err);
+            failure.get_or_insert(err);
+            None
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::cli::cmd_restore::wait_for_restore;
+use std::time::Instant;
+
+use crate::cli::hooks::{run_failure_hook, run_hook, HookEnv};
+use crate::cli::notify::{notify, Notice};
+use crate::cli::output::Output;
+use crate::cli::progress::Progress;
+use crate::cli::snapshot::Snapshot;
+use sab::config::{Backup, Config, Hooks, Notifications};
+use sab::download::{DownloadSummary, Downloader};
+use sab::error::{Result, ResultExt, SabError};
+use sab::event::Event;
+use sab::s3::S3Client;
+
+use tokio::io::AsyncWrite;
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_download(
+    cl: S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    out_file: &str,
+    encryption_key: Vec<u8>,
+    wait: bool,
+    hooks: &Hooks,
+    notifications: &Notifications,
+    cfg: &Config,
+    output: &Output,
+) -> Result<()> {
+    // Like verify, no lock is taken: the state is only read, and it's replaced atomically.
+    // Holding one through a restore wait would block the backup's uploads for hours.
+    let backup = load_backup(&cl, name, snapshot, cfg)?;
+    let started = Instant::now();
+    let mut env = HookEnv::new("download", name, &backup.name);
+
+    let res = async {
+        let mut f = tokio::fs::File::create(out_file)
+            .await
+            .or_io("failed to create the output file")?;
+
+        log::info!("starting download of {}", &backup.name);
+
+        fetch_backup(
+            &cl,
+            &backup,
+            name,
+            encryption_key,
+            wait,
+            &mut f,
+            "downloaded",
+            output,
+        )
+        .await
+    }
+    .await;
+
+    let notice = Notice::new(
+        "download",
+        &backup.name,
+        started.elapsed(),
+        res.as_ref().map(|summary| summary.size),
+    );
+    notify(notifications, &notice).await;
+
+    let summary = match res {
+        Ok(summary) => summary,
+        Err(err) => {
+            run_failure_hook(hooks, &mut env, &err).await;
+            return Err(err);
+        }
+    };
+
+    log::info!("backup successfully downloaded");
+
+    env.size = Some(summary.size);
+    env.sha256 = Some(summary.sha256);
+    run_hook("post_download", hooks.post_download.as_ref(), &env).await
+}
+
+/// Load the completed snapshot of the backup
+pub fn load_backup(
+    cl: &S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    cfg: &Config,
+) -> Result<Backup> {
+    let snapshots = cfg
+        .snapshots(&cl.key(name))
+        .map_err(|e| e.context("failed to load backup"))?;
+    if snapshots.is_empty() {
+        return Err(SabError::NotFound(format!("no backup named {}", name)));
+    }
+
+    // Only a snapshot asked for by its ID may be one still being uploaded
+    let candidates: Vec<Backup> = match snapshot {
+        Snapshot::Id(_) => snapshots,
+        _ => snapshots.into_iter().filter(|b| b.done).collect(),
+    };
+    if candidates.is_empty() {
+        return Err(SabError::State(format!("backup {} is not completed", name)));
+    }
+
+    let backup = snapshot.select(name, candidates)?;
+
+    if !backup.done {
+        return Err(SabError::State(format!("backup {} is not completed", name)));
+    }
+
+    Ok(backup)
+}
+
+/// Download the backup into `out`, optionally waiting for an archived object to be restored
+#[allow(clippy::too_many_arguments)]
+pub async fn fetch_backup<W: AsyncWrite + Unpin>(
+    cl: &S3Client<'_>,
+    backup: &Backup,
+    name: &str,
+    encryption_key: Vec<u8>,
+    wait: bool,
+    out: &mut W,
+    action: &'static str,
+    output: &Output,
+) -> Result<DownloadSummary> {
+    if wait && backup.is_archived() {
+        wait_for_restore(cl, backup, name).await?;
+    }
+
+    let progress = output.progress(backup.processed_size());
+    let key = (!encryption_key.is_empty()).then_some(encryption_key);
+    let downloader = || {
+        let bar = progress.clone();
+        let out = output.clone();
+
+        Downloader::new(cl, backup)
+            .encryption_key(key.clone())
+            .on_event(move |event| {
+                out.event(event);
+                log_event(&bar, action, event)
+            })
+    };
+
+    let res = match downloader().download(&mut *out).await {
+        Err(SabError::Archived(_)) if wait => {
+            wait_for_restore(cl, backup, name).await?;
+
+            downloader().download(out).await
+        }
+        Err(SabError::Archived(_)) => Err(SabError::Archived(format!(
+            "backup is archived, run `sab restore-request {}` and download again once `sab restore-status {}` reports it restored, or use --wait",
+            name, name
+        ))),
+        res => res,
+    };
+
+    progress.finish();
+
+    res
+}
+
+fn log_event(progress: &Progress, action: &str, event: &Event) {
+    match event {
+        Event::Progress { bytes, .. } => progress.set_position(*bytes),
+        Event::ChunkDownloaded {
+            idx,
+            size,
+            progress: pct,
+            ..
+        } => progress.suspend(|| {
+            log::info!(
+                "{} chunk={}\tsize={}\tprogress={:.2}%",
+                action,
+                idx,
+                size,
+                pct
+            )
+        }),
+        _ => {}
+    }
+}
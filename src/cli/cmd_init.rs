@@ -0,0 +1,341 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::{stdin, stdout, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::cli::cmd_config::master_password;
+use crate::cli::cmd_doctor::cmd_doctor;
+use crate::cli::cmd_gen_key::gen_key;
+
+use sab::config::{Config, Profile, DEFAULT_STORAGE_CLASS, STORAGE_CLASSES};
+use sab::error::{Result, ResultExt, SabError};
+use sab::secret;
+
+/// Profile settings given on the command line or in SAB_* environment variables,
+/// the missing ones are prompted for
+#[derive(Default)]
+pub struct InitValues {
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub region: Option<String>,
+    pub bucket: Option<String>,
+    pub prefix: Option<String>,
+    pub storage_class: Option<String>,
+    pub sse: Option<String>,
+    pub sse_kms_key_id: Option<String>,
+    pub encryption_key_file: Option<PathBuf>,
+    pub no_encryption: bool,
+    /// Save the profile without checking it works
+    pub skip_check: bool,
+}
+
+pub async fn cmd_init(profile_name: &str, values: &InitValues) -> Result<()> {
+    let sab_dir = Config::sab_dir();
+    if !sab_dir.exists() {
+        fs::create_dir_all(sab_dir.as_path()).or_io("failed to create sab directory")?;
+    }
+
+    let profiles_file = Config::profiles_file();
+    let mut cfg = if !profiles_file.exists() {
+        Config::default()
+    } else {
+        Config::load_with(master_password)
+            .map_err(|e| e.context("failed to load profiles config"))?
+    };
+
+    if cfg.profile(profile_name).is_some() {
+        return Err(SabError::Config(format!(
+            "profile {} already exists, use `sab profile edit {}` to change it",
+            profile_name, profile_name
+        )));
+    }
+
+    let mut profile = Profile::default();
+    populate_profile(&mut profile, values)?;
+
+    if !values.skip_check {
+        if let Err(err) = cmd_doctor(&profile).await {
+            let save = stdin().is_terminal() && confirm("Profile check failed, save it anyway?")?;
+            if !save {
+                return Err(err.context("profile check failed"));
+            }
+        }
+    }
+
+    cfg.set_profile(profile_name, profile);
+    cfg.save()
+        .map_err(|e| e.context("failed to save profiles config"))?;
+
+    log::info!(
+        "profile '{}' added to {}",
+        profile_name,
+        Config::profiles_file().display()
+    );
+
+    Ok(())
+}
+
+/// Set the profile from the given values and prompt for the rest,
+/// the current values are the defaults
+pub fn populate_profile(profile: &mut Profile, values: &InitValues) -> Result<()> {
+    // New profiles are encrypted by default, existing ones keep their current setting
+    let encrypted = profile.bucket.is_empty() || !profile.encryption_key.is_empty();
+
+    profile.access_key = given(&values.access_key, || {
+        input_current("S3 Access Key", &profile.access_key)
+    })?;
+    profile.secret_key = given(&values.secret_key, || {
+        input_secret("S3 Secret Key", &profile.secret_key)
+    })?;
+
+    let region = match profile.region.as_str() {
+        "" => "us-east-1".to_string(),
+        region => region.to_string(),
+    };
+    profile.region = given(&values.region, || input_default("S3 Region", region))?;
+    profile.bucket = given(&values.bucket, || {
+        input_current("Bucket Name", &profile.bucket)
+    })?;
+    profile.prefix = given(&values.prefix, || {
+        input_default("Bucket Prefix for Backups", profile.prefix.clone())
+    })?;
+
+    let current_class = profile
+        .storage_class
+        .clone()
+        .unwrap_or_else(|| DEFAULT_STORAGE_CLASS.to_string());
+
+    loop {
+        let class = given(&values.storage_class, || {
+            input_default("Default Storage Class", current_class.clone())
+        })?;
+        if STORAGE_CLASSES.contains(&class.as_str()) {
+            profile.storage_class = Some(class);
+            break;
+        }
+
+        println!(
+            "storage class must be one of {}",
+            STORAGE_CLASSES.join(", ")
+        );
+    }
+
+    let current_sse = match (&profile.sse, &profile.sse_customer_key) {
+        (_, Some(_)) => "SSE-C".to_string(),
+        (Some(sse), None) => sse.clone(),
+        (None, None) => "none".to_string(),
+    };
+
+    loop {
+        let sse = given(&values.sse, || {
+            input_default(
+                "Server-Side Encryption (none, AES256, aws:kms, SSE-C)",
+                current_sse.clone(),
+            )
+        })?;
+
+        match sse.as_str() {
+            "none" => {
+                profile.sse = None;
+                profile.sse_customer_key = None;
+            }
+            "AES256" => {
+                profile.sse = Some(sse);
+                profile.sse_customer_key = None;
+            }
+            "aws:kms" => {
+                let key_id = profile.sse_kms_key_id.clone().unwrap_or_default();
+                let bucket_key = profile.sse_kms_key_id.is_none() || profile.sse_bucket_key;
+
+                profile.sse = Some(sse);
+                profile.sse_customer_key = None;
+                profile.sse_kms_key_id = Some(given(&values.sse_kms_key_id, || {
+                    input_current("KMS Key ID", &key_id)
+                })?);
+                profile.sse_bucket_key = input_default("Enable S3 Bucket Key?", bucket_key)?;
+            }
+            // Objects written with the current customer key can't be read with a new one
+            "SSE-C" => {
+                profile.sse = None;
+                if profile.sse_customer_key.is_none() {
+                    profile.sse_customer_key = Some(gen_key());
+                }
+            }
+            _ => {
+                println!("unknown server-side encryption {}", sse);
+                continue;
+            }
+        }
+
+        if profile.sse.as_deref() != Some("aws:kms") {
+            profile.sse_kms_key_id = None;
+            profile.sse_bucket_key = false;
+        }
+
+        break;
+    }
+
+    if let Some(path) = &values.encryption_key_file {
+        profile.encryption_key = read_encryption_key(path)?;
+        return Ok(());
+    }
+
+    let enc_enabled = !values.no_encryption && input_default("Enable Encryption?", encrypted)?;
+    if !enc_enabled {
+        if !profile.encryption_key.is_empty() {
+            println!("backups encrypted with the removed key can't be downloaded anymore");
+        }
+        profile.encryption_key = "".to_string();
+    } else if profile.encryption_key.is_empty() {
+        profile.encryption_key = gen_key();
+    }
+
+    Ok(())
+}
+
+fn given<F>(value: &Option<String>, ask: F) -> Result<String>
+where
+    F: FnOnce() -> Result<String>,
+{
+    match value {
+        Some(value) => Ok(value.clone()),
+        None => ask(),
+    }
+}
+
+// The file holds the hex encoded key, as printed by `sab gen-key`
+fn read_encryption_key(path: &Path) -> Result<String> {
+    let key = fs::read_to_string(path).or_io("failed to read encryption key file")?;
+    let key = key.trim();
+
+    match hex::decode(key) {
+        Ok(raw) if raw.len() == 32 => Ok(key.to_string()),
+        _ => Err(SabError::Crypto(
+            "encryption key file must contain a hex encoded 32 byte key".to_string(),
+        )),
+    }
+}
+
+pub fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N]: ", prompt);
+    let _ = stdout().flush();
+    let mut val = String::new();
+    stdin()
+        .read_line(&mut val)
+        .or_io("failed to read input line")?;
+
+    Ok(matches!(val.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+fn input(prompt: &str) -> Result<String> {
+    if !stdin().is_terminal() {
+        return Err(SabError::Config(format!(
+            "no value provided for {}, pass it as an option or a SAB_* environment variable",
+            prompt
+        )));
+    }
+
+    loop {
+        print!("{}: ", prompt);
+        let _ = stdout().flush();
+        let mut val = String::new();
+        let n = stdin()
+            .read_line(&mut val)
+            .or_io("failed to read input line")?;
+
+        if n == 0 {
+            return Err(SabError::Io(format!("no value provided for {}", prompt)));
+        }
+
+        val = val.trim_end().to_string();
+        if val.is_empty() {
+            continue;
+        }
+
+        return Ok(val);
+    }
+}
+
+/// Prompt for a password without echoing it
+pub fn input_password(prompt: &str) -> Result<String> {
+    if !stdin().is_terminal() {
+        return Err(SabError::Config(format!(
+            "no value provided for {}",
+            prompt
+        )));
+    }
+
+    rpassword::prompt_password(format!("{}: ", prompt)).or_io("failed to read password")
+}
+
+// Required value, prompting with the current one as the default when there's one
+fn input_current(prompt: &str, current: &str) -> Result<String> {
+    match current {
+        "" => input(prompt),
+        current => input_default(prompt, current.to_string()),
+    }
+}
+
+// Like input_current, without echoing the current secret
+fn input_secret(prompt: &str, current: &str) -> Result<String> {
+    match current {
+        "" => input(prompt),
+        current => input_default(prompt, Masked(current.to_string())).map(|secret| secret.0),
+    }
+}
+
+struct Masked(String);
+
+impl Display for Masked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", mask(&self.0))
+    }
+}
+
+impl FromStr for Masked {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(Masked(s.to_string()))
+    }
+}
+
+/// Hide a secret, keeping its last characters to tell secrets apart
+pub fn mask(secret: &str) -> String {
+    // References don't reveal the secret
+    if secret::is_reference(secret) {
+        return secret.to_string();
+    }
+
+    match secret.char_indices().rev().nth(3) {
+        Some((idx, _)) if secret.len() >= 12 => format!("****{}", &secret[idx..]),
+        _ => "****".to_string(),
+    }
+}
+
+fn input_default<T>(prompt: &str, def: T) -> Result<T>
+where
+    T: Display + FromStr,
+    <T as FromStr>::Err: Display,
+{
+    // Nobody to ask, go with the default
+    if !stdin().is_terminal() {
+        return Ok(def);
+    }
+
+    print!("{} [{}]:", prompt, def);
+    let _ = stdout().flush();
+    let mut val = String::new();
+    stdin()
+        .read_line(&mut val)
+        .or_io("failed to read input line")?;
+    val = val.trim_end().to_string();
+
+    if val.is_empty() {
+        Ok(def)
+    } else {
+        val.parse()
+            .or_config(&format!("failed to parse value {}", val))
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use sab::config::{Backup, Config, ARCHIVE_STORAGE_CLASSES};
+use sab::error::{Result, SabError};
+use sab::s3::S3Client;
+
+pub async fn cmd_list(cl: S3Client<'_>, cfg: &Config) -> Result<()> {
+    let uploads = cl.list_uploads().await?;
+
+    let backups: HashMap<String, Backup> = cfg
+        .backups()
+        .map_err(|e| e.context("failed to load backups"))?
+        .into_iter()
+        .map(|backup| (backup.name.clone(), backup))
+        .collect();
+
+    uploads.iter().for_each(|(upload, remote_class)| {
+        let backup = backups.get(upload);
+
+        let class = match backup {
+            Some(backup) if !backup.storage_class.is_empty() => &backup.storage_class,
+            _ => remote_class,
+        };
+
+        let mut line = format!("* {}\t{}", upload, class);
+
+        if ARCHIVE_STORAGE_CLASSES.contains(&class.as_str()) {
+            line.push_str("\t(needs restore)");
+        }
+
+        if let Some(backup) = backup.filter(|backup| !backup.tags.is_empty()) {
+            let tags = backup
+                .tags
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join(",");
+
+            line.push_str(&format!("\t[{}]", tags));
+        }
+
+        println!("{}", line);
+    });
+
+    Ok(())
+}
+
+pub fn cmd_snapshots(cl: S3Client<'_>, name: &str, cfg: &Config) -> Result<()> {
+    let snapshots = cfg
+        .snapshots(&cl.key(name))
+        .map_err(|e| e.context("failed to load backup"))?;
+
+    if snapshots.is_empty() {
+        return Err(SabError::NotFound(format!("no backup named {}", name)));
+    }
+
+    for backup in snapshots {
+        let status = match backup.done {
+            true => "completed",
+            false => "in progress",
+        };
+
+        println!(
+            "* {}\t{}\t{}\t{}",
+            &backup.snapshot,
+            status,
+            backup.size(),
+            &backup.name
+        );
+    }
+
+    Ok(())
+}
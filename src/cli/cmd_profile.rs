@@ -0,0 +1,98 @@
+use crate::cli::cmd_init::{confirm, mask, populate_profile, InitValues};
+use sab::config::{Config, Profile};
+use sab::error::{Result, SabError};
+
+pub fn cmd_profile_list(cfg: &Config) -> Result<()> {
+    for name in cfg.profile_names() {
+        let profile = get_profile(cfg, name)?;
+        println!("* {}\t{}/{}", name, &profile.bucket, &profile.prefix);
+    }
+
+    Ok(())
+}
+
+/// Print the profile with the secrets masked
+pub fn cmd_profile_show(name: &str, cfg: &Config) -> Result<()> {
+    let mut profile = get_profile(cfg, name)?.clone();
+
+    profile.secret_key = mask(&profile.secret_key);
+    profile.encryption_key = mask(&profile.encryption_key);
+    profile.sse_customer_key = profile.sse_customer_key.as_deref().map(mask);
+    if let Some(email) = &mut profile.notify.email {
+        email.password = email.password.as_deref().map(mask);
+    }
+
+    print!("{}", serde_yaml::to_string(&profile)?);
+
+    Ok(())
+}
+
+pub fn cmd_profile_edit(name: &str, mut cfg: Config) -> Result<()> {
+    let mut profile = get_profile(&cfg, name)?.clone();
+    populate_profile(&mut profile, &InitValues::default())?;
+
+    cfg.set_profile(name, profile);
+    save(&cfg)?;
+
+    log::info!("profile '{}' updated", name);
+
+    Ok(())
+}
+
+pub fn cmd_profile_delete(name: &str, yes: bool, mut cfg: Config) -> Result<()> {
+    get_profile(&cfg, name)?;
+
+    if !yes && !confirm(&format!("Delete profile {}?", name))? {
+        log::info!("aborted");
+        return Ok(());
+    }
+
+    cfg.remove_profile(name);
+    save(&cfg)?;
+
+    log::info!("profile '{}' deleted", name);
+
+    Ok(())
+}
+
+pub fn cmd_profile_rename(from: &str, to: &str, mut cfg: Config) -> Result<()> {
+    check_available(&cfg, to)?;
+
+    let profile = get_profile(&cfg, from)?.clone();
+    cfg.remove_profile(from);
+    cfg.set_profile(to, profile);
+    save(&cfg)?;
+
+    log::info!("profile '{}' renamed to '{}'", from, to);
+
+    Ok(())
+}
+
+pub fn cmd_profile_copy(from: &str, to: &str, mut cfg: Config) -> Result<()> {
+    check_available(&cfg, to)?;
+
+    let profile = get_profile(&cfg, from)?.clone();
+    cfg.set_profile(to, profile);
+    save(&cfg)?;
+
+    log::info!("profile '{}' copied to '{}'", from, to);
+
+    Ok(())
+}
+
+fn get_profile<'a>(cfg: &'a Config, name: &str) -> Result<&'a Profile> {
+    cfg.profile(name)
+        .ok_or_else(|| SabError::Config(format!("unknown profile {}", name)))
+}
+
+fn check_available(cfg: &Config, name: &str) -> Result<()> {
+    match cfg.profile(name) {
+        Some(_) => Err(SabError::Config(format!("profile {} already exists", name))),
+        None => Ok(()),
+    }
+}
+
+fn save(cfg: &Config) -> Result<()> {
+    cfg.save()
+        .map_err(|e| e.context("failed to save profiles config"))
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use crate::cli::cmd_download::load_backup;
+use crate::cli::snapshot::Snapshot;
+use sab::config::{Backup, Config};
+use sab::error::{Result, SabError};
+use sab::s3::{RestoreStatus, S3Client};
+
+use aws_sdk_s3::model::Tier;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(300);
+
+pub async fn cmd_restore_request(
+    cl: S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    days: i32,
+    tier: Tier,
+    cfg: &Config,
+) -> Result<()> {
+    let backup = load_backup(&cl, name, snapshot, cfg)?;
+
+    if !backup.storage_class.is_empty() && !backup.is_archived() {
+        log::info!(
+            "{} uses {} storage class, no restore needed",
+            &backup.name,
+            &backup.storage_class
+        );
+        return Ok(());
+    }
+
+    cl.restore(&backup, days, tier).await?;
+
+    log::info!(
+        "restore of {} requested, check progress with `sab restore-status {} --snapshot {}`",
+        &backup.name,
+        name,
+        &backup.snapshot
+    );
+
+    Ok(())
+}
+
+pub async fn cmd_restore_status(
+    cl: S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    cfg: &Config,
+) -> Result<()> {
+    let backup = load_backup(&cl, name, snapshot, cfg)?;
+
+    let status = cl.restore_status(&backup).await?;
+
+    match status {
+        RestoreStatus::NotArchived => println!("{} is not archived", &backup.name),
+        RestoreStatus::NotRequested => {
+            println!("{} is archived, restore not requested", &backup.name)
+        }
+        RestoreStatus::InProgress => println!("{} restore in progress", &backup.name),
+        RestoreStatus::Restored(expiry) => {
+            println!("{} restored, available until {}", &backup.name, expiry)
+        }
+    }
+
+    Ok(())
+}
+
+/// Block until a previously requested restore completes
+pub async fn wait_for_restore(cl: &S3Client<'_>, backup: &Backup, name: &str) -> Result<()> {
+    loop {
+        let status = cl.restore_status(backup).await?;
+
+        match status {
+            RestoreStatus::NotArchived | RestoreStatus::Restored(_) => return Ok(()),
+            RestoreStatus::NotRequested => {
+                return Err(SabError::Archived(format!(
+                    "backup is archived and no restore was requested, run `sab restore-request {}` first",
+                    name
+                )))
+            }
+            RestoreStatus::InProgress => {
+                log::info!(
+                    "restore in progress, checking again in {}s",
+                    POLL_INTERVAL.as_secs()
+                );
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
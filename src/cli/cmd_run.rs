@@ -0,0 +1,461 @@
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::cli::cmd_delete::delete_backup;
+use crate::cli::cmd_upload::log_event;
+use crate::cli::hooks::{run_failure_hook, run_hook, HookEnv};
+use crate::cli::metrics::Recorder;
+use crate::cli::notify::{notify, Notice};
+use crate::cli::output::Output;
+use crate::cli::progress::Progress;
+use crate::cli::snapshot::new_snapshot_id;
+use crate::cli::template::render_name;
+use sab::config::{
+    snapshot_key, Backup, BackupLock, Config, Job, JobStatus, Jobs, Source, Status,
+    DEFAULT_STORAGE_CLASS, STORAGE_CLASSES,
+};
+use sab::error::{Result, ResultExt, SabError};
+use sab::s3::S3Client;
+use sab::upload::{Uploader, DEFAULT_CHUNK_SIZE};
+
+use chrono::Utc;
+use humanize_rs::bytes::Bytes;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::process::{Child, ChildStdout, Command};
+
+const DEFAULT_NAME: &str = "{job}";
+
+pub struct JobReport {
+    pub job: String,
+    pub duration: Duration,
+    pub result: Result<Backup>,
+}
+
+/// Run the named job from the jobs file, or all of them, and print a summary
+pub async fn cmd_run(
+    job: Option<&str>,
+    cfg: &Config,
+    output: &Output,
+    metrics_file: Option<&Path>,
+) -> Result<()> {
+    let jobs = Jobs::load().map_err(|e| SabError::Config(format!("failed to load jobs: {}", e)))?;
+
+    let selected: Vec<(&String, &Job)> = match job {
+        Some(name) => vec![jobs
+            .jobs
+            .get_key_value(name)
+            .ok_or_else(|| SabError::NotFound(format!("no job named {}", name)))?],
+        None => jobs.jobs.iter().collect(),
+    };
+
+    let mut reports = vec![];
+
+    for (name, job) in selected {
+        reports.push(run_and_record(name, job, cfg, output, metrics_file).await);
+    }
+
+    for report in &reports {
+        match &report.result {
+            Ok(backup) => println!(
+                "* {}\tok\t{}\t{}\t{:.1}s",
+                &report.job,
+                &backup.name,
+                backup.size(),
+                report.duration.as_secs_f64()
+            ),
+            Err(err) => println!(
+                "* {}\tfailed\t{}\t{:.1}s",
+                &report.job,
+                err,
+                report.duration.as_secs_f64()
+            ),
+        }
+    }
+
+    let failed = reports.iter().filter(|r| r.result.is_err()).count();
+
+    match reports
+        .into_iter()
+        .find_map(|r| r.result.err().map(|e| (r.job, e)))
+    {
+        Some((job, err)) if failed == 1 => Err(err.context(&format!("job {} failed", job))),
+        Some((_, err)) => Err(err.context(&format!("{} jobs failed, first error", failed))),
+        None => Ok(()),
+    }
+}
+
+/// Run the job and record the outcome in the status file and the metrics
+pub async fn run_and_record(
+    name: &str,
+    job: &Job,
+    cfg: &Config,
+    output: &Output,
+    metrics_file: Option<&Path>,
+) -> JobReport {
+    log::info!("running job {}", name);
+
+    let recorder = Recorder::new(&job.profile, metrics_file);
+    let started = Instant::now();
+    let mut object_key = None;
+    let result = run_job(name, job, cfg, output, &recorder, &mut object_key).await;
+    recorder.record(name, started.elapsed(), &result);
+
+    let targets = match (&job.notify, cfg.profile(&job.profile)) {
+        (Some(targets), _) => targets.clone(),
+        (None, Some(profile)) => profile.notify.clone(),
+        (None, None) => Default::default(),
+    };
+    // The job name only stands in when the run failed before the backup was named
+    let notice = Notice::new(
+        "upload",
+        object_key.as_deref().unwrap_or(name),
+        started.elapsed(),
+        result.as_ref().map(Backup::size),
+    )
+    .job(name);
+    notify(&targets, &notice).await;
+
+    let report = JobReport {
+        job: name.to_string(),
+        duration: started.elapsed(),
+        result,
+    };
+
+    if let Err(err) = &report.result {
+        log::error!("job {} failed: {}", name, err);
+    }
+
+    if let Err(err) = record_status(&report) {
+        log::warn!("failed to update the status file: {}", err);
+    }
+
+    report
+}
+
+fn record_status(report: &JobReport) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+
+    Status::update(|status| {
+        let job = status
+            .jobs
+            .entry(report.job.clone())
+            .or_insert_with(JobStatus::default);
+        job.last_run = now.clone();
+        job.duration = report.duration.as_secs_f64();
+
+        match &report.result {
+            Ok(backup) => {
+                job.last_success = Some(now);
+                job.last_backup = Some(backup.name.clone());
+            }
+            Err(err) => {
+                job.last_failure = Some(now);
+                job.last_error = Some(err.to_string());
+            }
+        }
+    })
+}
+
+/// Jobs with an upload interrupted before it completed
+pub fn interrupted_jobs(cfg: &Config) -> Result<Vec<String>> {
+    Ok(cfg
+        .backups()
+        .map_err(|e| e.context("failed to load backups"))?
+        .into_iter()
+        .filter(|backup| !backup.done)
+        .filter_map(|backup| backup.job)
+        .collect())
+}
+
+async fn run_job(
+    job_name: &str,
+    job: &Job,
+    cfg: &Config,
+    output: &Output,
+    recorder: &Recorder,
+    object_key_out: &mut Option<String>,
+) -> Result<Backup> {
+    let profile = cfg
+        .profile(&job.profile)
+        .ok_or_else(|| SabError::Config(format!("unknown profile {}", &job.profile)))?;
+    let cl = S3Client::new(profile).await?;
+
+    let chunk_size = match &job.chunk_size {
+        Some(size) => size
+            .parse::<Bytes>()
+            .or_config("failed to parse chunk size")?
+            .size(),
+        None => DEFAULT_CHUNK_SIZE,
+    };
+
+    let encryption_key = match job.encryption {
+        true => Some(profile.resolve_encryption_key()?),
+        false => None,
+    };
+
+    let class = job
+        .storage_class
+        .clone()
+        .or_else(|| profile.storage_class.clone())
+        .unwrap_or_else(|| DEFAULT_STORAGE_CLASS.to_string());
+    if !STORAGE_CLASSES.contains(&class.as_str()) {
+        return Err(SabError::Config(format!("unknown storage class {}", class)));
+    }
+
+    let mut tags = profile.tags.clone();
+    tags.extend(job.tags.clone());
+
+    let hooks = job.hooks.with_defaults(&profile.hooks);
+
+    let interrupted = cfg
+        .backups()
+        .map_err(|e| e.context("failed to load backups"))?
+        .into_iter()
+        .find(|backup| !backup.done && backup.job.as_deref() == Some(job_name));
+
+    let (name, key, resumed) = match interrupted {
+        // Only a file can be read again to resume the upload
+        Some(backup) if matches!(job.source, Source::File(_)) => {
+            log::info!("resuming upload {}", &backup.name);
+
+            let key = backup.base_key().to_string();
+            let name = key.strip_prefix(backup.prefix.as_str()).unwrap_or(&key);
+
+            (name.to_string(), key, Some(backup))
+        }
+        interrupted => {
+            if let Some(backup) = interrupted {
+                log::info!("discarding interrupted upload {}", &backup.name);
+
+                let dir = cfg.backup_dir(backup.base_key());
+                let _lock = BackupLock::acquire(&dir, &backup.name, false).await?;
+                delete_backup(&cl, &backup, cfg.backup_file(&backup).as_path()).await?;
+            }
+
+            let template = job.name.as_deref().unwrap_or(DEFAULT_NAME);
+            let name = render_name(template, &[("job", job_name)], Utc::now())?;
+            let key = profile.prefix.to_string() + &name;
+
+            (name, key, None)
+        }
+    };
+
+    let lock = BackupLock::acquire(&cfg.backup_dir(&key), &name, false).await?;
+
+    // Every run of the job is a new snapshot of the backup
+    let (snapshot, object_key) = match resumed {
+        Some(backup) => (backup.snapshot, backup.name),
+        None => {
+            let latest = cfg
+                .snapshots(&key)
+                .map_err(|e| e.context("failed to load backup"))?
+                .pop();
+            let snapshot = new_snapshot_id(latest.as_ref())?;
+            let object_key = snapshot_key(&key, &snapshot);
+
+            (snapshot, object_key)
+        }
+    };
+    let backup_file = cfg.snapshot_file(&key, &snapshot);
+    *object_key_out = Some(object_key.clone());
+
+    log::info!("starting upload {}", &object_key);
+
+    let mut env = HookEnv::new("upload", &name, &object_key);
+
+    let res = async {
+        run_hook("pre_upload", hooks.pre_upload.as_ref(), &env).await?;
+
+        let (reader, size) = open_source(&job.source).await?;
+
+        let progress = match size {
+            Some(size) => output.progress(size),
+            None => Progress::hidden(),
+        };
+        let bar = progress.clone();
+        let out = output.clone();
+        let rec = recorder.clone();
+
+        let mut uploader = Uploader::new(&cl, reader, &object_key)
+            .prefix(&profile.prefix)
+            .chunk_size(chunk_size)
+            .compression(job.compression)
+            .encryption_key(encryption_key)
+            .storage_class(&class)
+            .tags(tags)
+            .state_file(backup_file.clone())
+            .snapshot(&snapshot)
+            .job(job_name)
+            .on_event(move |event| {
+                out.event(event);
+                rec.observe(event);
+                log_event(&bar, event)
+            });
+        if let Some(size) = size {
+            uploader = uploader.size_hint(size);
+        }
+
+        let res = uploader.upload().await;
+
+        progress.finish();
+        res
+    }
+    .await;
+
+    let backup = match res {
+        Ok(backup) => backup,
+        Err(err) => {
+            run_failure_hook(&hooks, &mut env, &err).await;
+            return Err(err);
+        }
+    };
+
+    log::info!("upload completed");
+
+    env.size = Some(backup.size());
+    env.sha256 = Some(backup.sha256.clone());
+    run_hook("post_upload", hooks.post_upload.as_ref(), &env).await?;
+
+    // Pruning locks the backups it deletes, this one included
+    drop(lock);
+
+    if let Some(keep) = job.retention {
+        prune(&cl, cfg, job_name, keep).await?;
+    }
+
+    Ok(backup)
+}
+
+type SourceReader = Box<dyn AsyncRead + Unpin + Send>;
+
+/// Open the job source, returning its size when it's known upfront
+async fn open_source(source: &Source) -> Result<(SourceReader, Option<u64>)> {
+    match source {
+        Source::File(path) => {
+            let f = tokio::fs::File::open(path)
+                .await
+                .or_io("failed to open upload file")?;
+            let size = f
+                .metadata()
+                .await
+                .or_io("failed to get input file metadata")?
+                .len();
+
+            Ok((Box::new(f), Some(size)))
+        }
+        Source::Dir(path) => {
+            let mut cmd = Command::new("tar");
+            cmd.arg("-C").arg(path).arg("-cf").arg("-").arg(".");
+
+            Ok((Box::new(CommandReader::spawn(cmd)?), None))
+        }
+        Source::Command(command) => {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(command);
+
+            Ok((Box::new(CommandReader::spawn(cmd)?), None))
+        }
+    }
+}
+
+/// Delete the oldest completed backups of the job, keeping `keep` most recent ones
+async fn prune(cl: &S3Client<'_>, cfg: &Config, job: &str, keep: usize) -> Result<()> {
+    let mut backups: Vec<Backup> = cfg
+        .backups()
+        .map_err(|e| e.context("failed to load backups"))?
+        .into_iter()
+        .filter(|backup| backup.done && backup.job.as_deref() == Some(job))
+        .collect();
+
+    backups.sort_by(|a, b| b.completed.cmp(&a.completed));
+
+    for backup in backups.into_iter().skip(keep) {
+        if backup.is_locked() {
+            log::warn!("backup {} is locked, keeping it", &backup.name);
+            continue;
+        }
+
+        let dir = cfg.backup_dir(backup.base_key());
+        let _lock = match BackupLock::acquire(&dir, &backup.name, false).await {
+            Ok(lock) => lock,
+            Err(err) => {
+                log::warn!("{}, keeping it", err);
+                continue;
+            }
+        };
+
+        delete_backup(cl, &backup, cfg.backup_file(&backup).as_path()).await?;
+    }
+
+    Ok(())
+}
+
+/// Standard output of a command, a non-zero exit status is reported as a read error
+/// once the output is exhausted, so a failed command never completes an upload.
+/// The command is killed when the reader is dropped before that.
+struct CommandReader {
+    child: Child,
+    stdout: ChildStdout,
+    exited: bool,
+}
+
+impl CommandReader {
+    fn spawn(mut cmd: Command) -> Result<Self> {
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .or_io("failed to start source command")?;
+
+        let stdout = child.stdout.take().unwrap();
+
+        Ok(CommandReader {
+            child,
+            stdout,
+            exited: false,
+        })
+    }
+}
+
+impl AsyncRead for CommandReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+
+        match Pin::new(&mut self.stdout).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) if buf.filled().len() == filled => {}
+            res => return res,
+        }
+
+        if self.exited {
+            return Poll::Ready(Ok(()));
+        }
+
+        let status = {
+            let wait = self.child.wait();
+            tokio::pin!(wait);
+            wait.poll(cx)
+        };
+
+        match status {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Ok(status)) if status.success() => {
+                self.exited = true;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Ok(status)) => Poll::Ready(Err(io::Error::other(format!(
+                "source command failed: {}",
+                status
+            )))),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+        }
+    }
+}
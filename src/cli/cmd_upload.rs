@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::cli::hooks::{run_failure_hook, run_hook, HookEnv};
+use crate::cli::metrics::Recorder;
+use crate::cli::notify::{notify, Notice};
+use crate::cli::output::Output;
+use crate::cli::progress::Progress;
+use crate::cli::snapshot::new_snapshot_id;
+use crate::cli::template::{render_name, series_name};
+use sab::config::{snapshot_key, Backup, BackupLock, Config, Hooks, Notifications, ObjectLock};
+use sab::error::{Result, ResultExt, SabError};
+use sab::event::Event;
+use sab::s3::S3Client;
+use sab::upload::Uploader;
+
+use aws_sdk_s3::model::StorageClass;
+use chrono::Utc;
+
+/// Backups are named after the uploaded file by default
+const DEFAULT_NAME: &str = "{basename}";
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_upload(
+    cl: S3Client<'_>,
+    file: &str,
+    name_template: Option<&str>,
+    chunk_size: usize,
+    compression_enabled: bool,
+    encryption_enabled: bool,
+    encryption_key: Vec<u8>,
+    prefix: String,
+    class: StorageClass,
+    tags: BTreeMap<String, String>,
+    metadata: BTreeMap<String, String>,
+    lock: ObjectLock,
+    wait: bool,
+    hooks: &Hooks,
+    notifications: &Notifications,
+    cfg: &Config,
+    output: &Output,
+    recorder: &Recorder,
+) -> Result<()> {
+    let input_file = PathBuf::from(file);
+    let basename = input_file
+        .file_name()
+        .ok_or_else(|| SabError::Config(format!("{} is not a file", file)))?
+        .to_string_lossy()
+        .to_string();
+
+    let template = name_template.unwrap_or(DEFAULT_NAME);
+    let name = render_name(template, &[("basename", &basename)], Utc::now())?;
+    let series = series_name(template, &[("basename", &basename)])?;
+    let key = prefix.to_string() + &name;
+
+    // Locked before the hook runs, which may rewrite the file another upload is reading
+    let _lock = BackupLock::acquire(&cfg.backup_dir(&key), &name, wait).await?;
+
+    let started = Instant::now();
+    let mut env = HookEnv::new("upload", &name, &key);
+
+    let res = async {
+        // The hook may produce the file, so nothing looks at it before
+        run_hook("pre_upload", hooks.pre_upload.as_ref(), &env).await?;
+
+        let md = input_file
+            .metadata()
+            .or_io("failed to get input file metadata")?;
+
+        // Resume the latest snapshot if its upload is pending, otherwise take a new one
+        let latest = cfg
+            .snapshots(&key)
+            .map_err(|e| e.context("failed to load backup"))?
+            .pop();
+
+        let (snapshot, object_key) = match latest {
+            Some(backup) if !backup.done => {
+                log::info!("loading existing configuration");
+                (backup.snapshot, backup.name)
+            }
+            latest => {
+                log::info!("creating new configuration");
+                let snapshot = new_snapshot_id(latest.as_ref())?;
+                let object_key = snapshot_key(&key, &snapshot);
+                (snapshot, object_key)
+            }
+        };
+        let backup_file = cfg.snapshot_file(&key, &snapshot);
+
+        log::info!("starting upload {}", &object_key);
+        env.key = object_key.clone();
+
+        let f = tokio::fs::File::open(file)
+            .await
+            .or_io("failed to open upload file")?;
+
+        let progress = output.progress(md.len());
+        let bar = progress.clone();
+        let out = output.clone();
+        let rec = recorder.clone();
+
+        let res = Uploader::new(&cl, f, &object_key)
+            .prefix(&prefix)
+            .chunk_size(chunk_size)
+            .compression(compression_enabled)
+            .encryption_key(encryption_enabled.then_some(encryption_key))
+            .storage_class(class.as_str())
+            .tags(tags)
+            .metadata(metadata)
+            .lock(lock)
+            .size_hint(md.len())
+            .state_file(backup_file)
+            .snapshot(&snapshot)
+            .on_event(move |event| {
+                out.event(event);
+                rec.observe(event);
+                log_event(&bar, event)
+            })
+            .upload()
+            .await;
+
+        progress.finish();
+        res
+    }
+    .await;
+
+    let res = match res {
+        Ok(backup) => {
+            log::info!("upload completed");
+
+            env.size = Some(backup.size());
+            env.sha256 = Some(backup.sha256.clone());
+            run_hook("post_upload", hooks.post_upload.as_ref(), &env)
+                .await
+                .map(|_| backup)
+        }
+        Err(err) => {
+            run_failure_hook(hooks, &mut env, &err).await;
+            Err(err)
+        }
+    };
+
+    recorder.record(&series, started.elapsed(), &res);
+
+    let notice = Notice::new(
+        "upload",
+        &env.key,
+        started.elapsed(),
+        res.as_ref().map(Backup::size),
+    );
+    notify(notifications, &notice).await;
+
+    res.map(|_| ())
+}
+
+pub fn log_event(progress: &Progress, event: &Event) {
+    match event {
+        Event::Progress { bytes, .. } => progress.set_position(*bytes),
+        Event::ChunkSkipped { idx } => {
+            progress.suspend(|| log::info!("chunk {} already uploaded, skipping", idx))
+        }
+        Event::ChunkUploaded {
+            idx,
+            original_size,
+            processed_size,
+            progress: pct,
+            ..
+        } => progress.suspend(|| {
+            log::info!(
+                "uploaded chunk={}\torig-size={}\tprocessed-size={}\tprogress={:.2}%",
+                idx,
+                original_size,
+                processed_size,
+                pct.unwrap_or_default()
+            )
+        }),
+        Event::Retry {
+            idx,
+            attempt,
+            error,
+        } => progress.suspend(|| {
+            log::warn!(
+                "failed to upload chunk {}, retrying (attempt {}): {}",
+                idx,
+                attempt,
+                error
+            )
+        }),
+        _ => {}
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Instant;
+
+use crate::cli::cmd_download::{fetch_backup, load_backup};
+use crate::cli::notify::{notify, Notice};
+use crate::cli::output::Output;
+use crate::cli::snapshot::Snapshot;
+use sab::config::{Backup, Config, Notifications};
+use sab::error::{Result, SabError};
+use sab::s3::S3Client;
+
+#[allow(clippy::too_many_arguments)]
+pub async fn cmd_verify(
+    cl: S3Client<'_>,
+    name: &str,
+    snapshot: &Snapshot,
+    quick: bool,
+    encryption_key: Vec<u8>,
+    notifications: &Notifications,
+    cfg: &Config,
+    output: &Output,
+) -> Result<()> {
+    let backup = load_backup(&cl, name, snapshot, cfg)?;
+    let started = Instant::now();
+
+    let res = verify(&cl, &backup, name, quick, encryption_key, output).await;
+
+    let notice = Notice::new(
+        "verify",
+        &backup.name,
+        started.elapsed(),
+        res.as_ref().map(|_| backup.size()),
+    );
+    notify(notifications, &notice).await;
+
+    res
+}
+
+async fn verify(
+    cl: &S3Client<'_>,
+    backup: &Backup,
+    name: &str,
+    quick: bool,
+    encryption_key: Vec<u8>,
+    output: &Output,
+) -> Result<()> {
+    if quick {
+        let head = cl.head(backup).await?;
+
+        let expected_size = backup.processed_size();
+        let size = head.content_length() as u64;
+        if size != expected_size {
+            return Err(SabError::Integrity(format!(
+                "backup size mismatch, expected={}, got={}",
+                expected_size, size
+            )));
+        }
+
+        // Backups uploaded before the final etag was recorded can only be checked by size
+        let etag = head.e_tag().unwrap_or_default();
+        if !backup.etag.is_empty() && etag != backup.etag {
+            return Err(SabError::Integrity(format!(
+                "backup etag mismatch, expected={}, got={}",
+                &backup.etag, etag
+            )));
+        }
+
+        log::info!("backup size and etag match");
+        return Ok(());
+    }
+
+    log::info!("starting verification");
+
+    let mut out = tokio::io::sink();
+    fetch_backup(
+        cl,
+        backup,
+        name,
+        encryption_key,
+        false,
+        &mut out,
+        "verified",
+        output,
+    )
+    .await?;
+
+    log::info!("backup successfully verified");
+
+    Ok(())
+}
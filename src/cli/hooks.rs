@@ -0,0 +1,77 @@
+use sab::config::Hooks;
+use sab::error::{Result, SabError};
+
+use tokio::process::Command;
+
+/// Backup details passed to hook commands as SAB_* environment variables
+pub struct HookEnv {
+    pub command: &'static str,
+    pub name: String,
+    pub key: String,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub error: Option<String>,
+}
+
+impl HookEnv {
+    pub fn new(command: &'static str, name: &str, key: &str) -> Self {
+        HookEnv {
+            command,
+            name: name.to_string(),
+            key: key.to_string(),
+            size: None,
+            sha256: None,
+            error: None,
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.error, &self.sha256) {
+            (Some(_), _) => "failure",
+            (None, Some(_)) => "success",
+            (None, None) => "pending",
+        }
+    }
+}
+
+/// Run the hook command with `sh -c`, a non-zero exit status is an error
+pub async fn run_hook(hook: &str, cmd: Option<&String>, env: &HookEnv) -> Result<()> {
+    let Some(cmd) = cmd else {
+        return Ok(());
+    };
+
+    log::info!("running {} hook", hook);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("SAB_HOOK", hook)
+        .env("SAB_COMMAND", env.command)
+        .env("SAB_NAME", &env.name)
+        .env("SAB_KEY", &env.key)
+        .env(
+            "SAB_SIZE",
+            env.size.map(|s| s.to_string()).unwrap_or_default(),
+        )
+        .env("SAB_SHA256", env.sha256.as_deref().unwrap_or_default())
+        .env("SAB_STATUS", env.status())
+        .env("SAB_ERROR", env.error.as_deref().unwrap_or_default())
+        .status()
+        .await
+        .map_err(|e| SabError::Hook(format!("failed to run {} hook: {}", hook, e)))?;
+
+    if !status.success() {
+        return Err(SabError::Hook(format!("{} hook failed: {}", hook, status)));
+    }
+
+    Ok(())
+}
+
+/// Run the on_failure hook, its own failure is only logged so the original error is kept
+pub async fn run_failure_hook(hooks: &Hooks, env: &mut HookEnv, err: &SabError) {
+    env.error = Some(err.to_string());
+
+    if let Err(err) = run_hook("on_failure", hooks.on_failure.as_ref(), env).await {
+        log::warn!("{}", err);
+    }
+}
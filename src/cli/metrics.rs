@@ -0,0 +1,221 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use sab::config::{Backup, BackupMetrics, Metrics};
+use sab::error::{Result, ResultExt};
+use sab::event::Event;
+
+use chrono::Utc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+type Metric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&BackupMetrics) -> f64,
+);
+
+const METRICS: [Metric; 8] = [
+    (
+        "sab_last_run_timestamp_seconds",
+        "gauge",
+        "Time of the last backup run",
+        |m| m.last_run as f64,
+    ),
+    (
+        "sab_last_success_timestamp_seconds",
+        "gauge",
+        "Time of the last successful backup run",
+        |m| m.last_success.unwrap_or_default() as f64,
+    ),
+    (
+        "sab_last_duration_seconds",
+        "gauge",
+        "Duration of the last backup run",
+        |m| m.duration,
+    ),
+    (
+        "sab_last_original_bytes",
+        "gauge",
+        "Original size of the last successful backup",
+        |m| m.original_bytes as f64,
+    ),
+    (
+        "sab_last_processed_bytes",
+        "gauge",
+        "Uploaded size of the last successful backup after compression and encryption",
+        |m| m.processed_bytes as f64,
+    ),
+    (
+        "sab_last_chunks",
+        "gauge",
+        "Number of chunks of the last successful backup",
+        |m| m.chunks as f64,
+    ),
+    (
+        "sab_retries_total",
+        "counter",
+        "Chunk upload retries",
+        |m| m.retries as f64,
+    ),
+    ("sab_failures_total", "counter", "Failed backup runs", |m| {
+        m.failures as f64
+    }),
+];
+
+/// Records upload runs into the metrics state and the optional textfile collector file
+#[derive(Clone)]
+pub struct Recorder {
+    profile: String,
+    textfile: Option<PathBuf>,
+    retries: Arc<AtomicU64>,
+}
+
+impl Recorder {
+    pub fn new(profile: &str, textfile: Option<&Path>) -> Self {
+        Recorder {
+            profile: profile.to_string(),
+            textfile: textfile.map(Path::to_path_buf),
+            retries: Default::default(),
+        }
+    }
+
+    /// Count the upload events that end up in the metrics
+    pub fn observe(&self, event: &Event) {
+        if let Event::Retry { .. } = event {
+            self.retries.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the run outcome, failing to do so is only logged
+    pub fn record(&self, name: &str, duration: Duration, result: &Result<Backup>) {
+        if let Err(err) = self.update(name, duration, result) {
+            log::warn!("failed to update metrics: {}", err);
+        }
+    }
+
+    fn update(&self, name: &str, duration: Duration, result: &Result<Backup>) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let retries = self.retries.swap(0, Ordering::Relaxed);
+
+        let metrics = Metrics::update(|metrics| {
+            let m = metrics
+                .backups
+                .entry(self.profile.clone())
+                .or_default()
+                .entry(name.to_string())
+                .or_default();
+
+            m.last_run = now;
+            m.duration = duration.as_secs_f64();
+            m.retries += retries;
+
+            match result {
+                Ok(backup) => {
+                    m.last_success = Some(now);
+                    m.original_bytes = backup.size();
+                    m.processed_bytes = backup.processed_size();
+                    m.chunks = backup.parts.len() as u64;
+                }
+                Err(_) => m.failures += 1,
+            }
+        })?;
+
+        match &self.textfile {
+            Some(path) => write_textfile(path, &render(&metrics)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Render the metrics in the Prometheus text exposition format
+pub fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+
+    for (metric, kind, help, value) in METRICS {
+        let _ = writeln!(out, "# HELP {} {}", metric, help);
+        let _ = writeln!(out, "# TYPE {} {}", metric, kind);
+
+        for (profile, backups) in &metrics.backups {
+            for (name, m) in backups {
+                let _ = writeln!(
+                    out,
+                    "{}{{profile=\"{}\",name=\"{}\"}} {}",
+                    metric,
+                    escape(profile),
+                    escape(name),
+                    value(m)
+                );
+            }
+        }
+    }
+
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// The collector may read the file at any time, so it's replaced atomically
+fn write_textfile(path: &Path, data: &str) -> Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+
+    fs::write(&tmp, data).or_io("failed to write metrics file")?;
+    fs::rename(&tmp, path).or_io("failed to replace metrics file")
+}
+
+/// Serve the metrics on `/metrics` over plain HTTP
+pub async fn serve(addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .or_io("failed to bind metrics listener")?;
+
+    log::info!("serving metrics on http://{}/metrics", addr);
+
+    loop {
+        let (mut conn, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::warn!("failed to accept metrics connection: {}", err);
+                continue;
+            }
+        };
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            let n = conn.read(&mut buf).await.unwrap_or_default();
+            let request = String::from_utf8_lossy(&buf[..n]);
+
+            let response = match request.split_whitespace().nth(1) {
+                Some("/metrics") => {
+                    let body = match Metrics::load() {
+                        Ok(metrics) => render(&metrics),
+                        Err(err) => {
+                            log::warn!("failed to load metrics: {}", err);
+                            String::new()
+                        }
+                    };
+
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                }
+                _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+
+            let _ = conn.write_all(response.as_bytes()).await;
+        });
+    }
+}
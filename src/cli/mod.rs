@@ -0,0 +1,20 @@
+pub mod cmd_config;
+pub mod cmd_daemon;
+pub mod cmd_delete;
+pub mod cmd_doctor;
+pub mod cmd_download;
+pub mod cmd_gen_key;
+pub mod cmd_init;
+pub mod cmd_list;
+pub mod cmd_profile;
+pub mod cmd_restore;
+pub mod cmd_run;
+pub mod cmd_upload;
+pub mod cmd_verify;
+pub mod hooks;
+pub mod metrics;
+pub mod notify;
+pub mod output;
+pub mod progress;
+pub mod snapshot;
+pub mod template;
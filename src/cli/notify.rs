@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use sab::config::{Email, Notifications};
+use sab::error::{Result, SabError};
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Serialize;
+
+/// Outcome of an upload, download or verification reported to the notification targets
+#[derive(Serialize)]
+pub struct Notice {
+    /// Slack message text
+    text: String,
+    /// completed, failed or integrity_mismatch
+    event: &'static str,
+    command: &'static str,
+    name: String,
+    size: Option<u64>,
+    duration: f64,
+    error: Option<String>,
+    /// Set for scheduled and `sab run` jobs
+    job: Option<String>,
+}
+
+impl Notice {
+    /// Describe the result of a command, `size` being the backup size when it succeeded
+    pub fn new(
+        command: &'static str,
+        name: &str,
+        duration: Duration,
+        result: std::result::Result<u64, &SabError>,
+    ) -> Self {
+        let (event, size, error) = match result {
+            Ok(size) => ("completed", Some(size), None),
+            Err(err @ SabError::Integrity(_)) => {
+                ("integrity_mismatch", None, Some(err.to_string()))
+            }
+            Err(err) => ("failed", None, Some(err.to_string())),
+        };
+
+        let text = match &error {
+            None => format!(
+                "sab {} of {} completed, {} bytes in {:.1}s",
+                command,
+                name,
+                size.unwrap_or_default(),
+                duration.as_secs_f64()
+            ),
+            Some(error) => format!("sab {} of {} failed: {}", command, name, error),
+        };
+
+        Notice {
+            text,
+            event,
+            command,
+            name: name.to_string(),
+            size,
+            duration: duration.as_secs_f64(),
+            error,
+            job: None,
+        }
+    }
+
+    /// Mark the notice as coming from a job run
+    pub fn job(mut self, job: &str) -> Self {
+        self.job = Some(job.to_string());
+        self
+    }
+}
+
+/// Send the notice to all the targets, failures are only logged
+pub async fn notify(targets: &Notifications, notice: &Notice) {
+    if targets.only_failures && notice.error.is_none() {
+        return;
+    }
+
+    if let Some(url) = &targets.webhook {
+        if let Err(err) = send_webhook(url, notice).await {
+            log::warn!("{}", err);
+        }
+    }
+
+    if let Some(email) = &targets.email {
+        if let Err(err) = send_email(email, notice).await {
+            log::warn!("{}", err);
+        }
+    }
+}
+
+async fn send_webhook(url: &str, notice: &Notice) -> Result<()> {
+    let fail = |e: reqwest::Error| SabError::Network(format!("failed to call webhook: {}", e));
+
+    reqwest::Client::new()
+        .post(url)
+        .json(notice)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(fail)?
+        .error_for_status()
+        .map_err(fail)?;
+
+    Ok(())
+}
+
+async fn send_email(email: &Email, notice: &Notice) -> Result<()> {
+    let config = |e: String| SabError::Config(format!("invalid email notification config: {}", e));
+
+    let mut msg = Message::builder()
+        .from(
+            email
+                .from
+                .parse::<Mailbox>()
+                .map_err(|e| config(e.to_string()))?,
+        )
+        .subject(&notice.text);
+    for to in &email.to {
+        msg = msg.to(to.parse::<Mailbox>().map_err(|e| config(e.to_string()))?);
+    }
+
+    let body = format!(
+        "{}\n\ncommand: {}\nname: {}\nsize: {}\nduration: {:.1}s\nerror: {}\njob: {}\n",
+        &notice.text,
+        notice.command,
+        &notice.name,
+        notice.size.map(|s| s.to_string()).unwrap_or_default(),
+        notice.duration,
+        notice.error.as_deref().unwrap_or_default(),
+        notice.job.as_deref().unwrap_or_default()
+    );
+
+    let msg = msg.body(body).map_err(|e| config(e.to_string()))?;
+
+    let mut transport = match email.security.as_str() {
+        "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&email.smtp_host),
+        "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.smtp_host),
+        "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            &email.smtp_host,
+        )),
+        other => return Err(config(format!("unknown security {}", other))),
+    }
+    .map_err(|e| config(e.to_string()))?;
+
+    if let Some(port) = email.smtp_port {
+        transport = transport.port(port);
+    }
+
+    if let (Some(username), Some(password)) = (&email.username, email.resolve_password()?) {
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(msg)
+        .await
+        .map_err(|e| SabError::Network(format!("failed to send email: {}", e)))?;
+
+    Ok(())
+}
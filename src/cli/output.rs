@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{stderr, Write};
+use std::os::fd::BorrowedFd;
+use std::sync::{Arc, Mutex};
+
+use crate::cli::progress::Progress;
+use sab::error::{Result, SabError};
+use sab::event::Event;
+
+use chrono::Utc;
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Record<'a, T: Serialize> {
+    time: String,
+    command: &'a str,
+    #[serde(flatten)]
+    event: T,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename = "error")]
+struct ErrorEvent<'a> {
+    class: &'a str,
+    exit_code: u8,
+    message: String,
+}
+
+/// Where command events go: log lines and progress bars for humans,
+/// or one JSON object per line for machines.
+#[derive(Clone)]
+pub struct Output {
+    command: &'static str,
+    json: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+}
+
+impl Output {
+    pub fn new(command: &'static str, format: OutputFormat, fd: Option<i32>) -> Result<Self> {
+        let json: Option<Box<dyn Write + Send>> = match (format, fd) {
+            (OutputFormat::Text, _) => None,
+            (OutputFormat::Json, None) => Some(Box::new(stderr())),
+            (OutputFormat::Json, Some(fd)) => Some(Box::new(open_fd(fd)?)),
+        };
+
+        Ok(Output {
+            command,
+            json: json.map(|w| Arc::new(Mutex::new(w))),
+        })
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.json.is_some()
+    }
+
+    /// Progress bar, hidden when emitting JSON
+    pub fn progress(&self, total: u64) -> Progress {
+        if self.is_json() {
+            Progress::hidden()
+        } else {
+            Progress::new(total)
+        }
+    }
+
+    /// Emit a JSON event, byte level progress is too chatty and is skipped
+    pub fn event(&self, event: &Event) {
+        if !matches!(event, Event::Progress { .. }) {
+            self.write(event);
+        }
+    }
+
+    pub fn error(&self, err: &SabError) {
+        self.write(ErrorEvent {
+            class: err.class(),
+            exit_code: err.code(),
+            message: err.to_string(),
+        });
+    }
+
+    fn write<T: Serialize>(&self, event: T) {
+        let Some(json) = &self.json else {
+            return;
+        };
+
+        let record = Record {
+            time: Utc::now().to_rfc3339(),
+            command: self.command,
+            event,
+        };
+
+        if let Ok(line) = serde_json::to_string(&record) {
+            let mut w = json.lock().unwrap();
+            let _ = writeln!(w, "{}", line);
+            let _ = w.flush();
+        }
+    }
+}
+
+// Duplicate the descriptor handed over by the caller, so that the events never end up in
+// a file sab opens itself when it isn't open, and the caller's one is never closed
+fn open_fd(fd: i32) -> Result<File> {
+    // Standard input and output are no place for the events
+    if fd <= 1 {
+        return Err(SabError::Config(format!(
+            "invalid output file descriptor {}",
+            fd
+        )));
+    }
+
+    // SAFETY: the descriptor is only borrowed to duplicate it, which fails when it isn't open
+    let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+    let owned = borrowed.try_clone_to_owned().map_err(|e| {
+        SabError::Config(format!("output file descriptor {} is not open: {}", fd, e))
+    })?;
+
+    Ok(File::from(owned))
+}
@@ -0,0 +1,66 @@
+use std::fmt::Write;
+use std::io::{stderr, IsTerminal};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressState, ProgressStyle};
+
+const TEMPLATE: &str =
+    "{wide_bar} {bytes}/{total_bytes} {bytes_per_sec} (avg {avg_rate}) eta {eta}";
+
+/// Byte level progress bar, only drawn when stderr is a terminal.
+///
+/// Otherwise all the methods are no-ops and the progress is reported by the
+/// regular per-chunk log lines.
+#[derive(Clone)]
+pub struct Progress {
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    pub fn new(total: u64) -> Self {
+        if !stderr().is_terminal() {
+            return Progress { bar: None };
+        }
+
+        let style = ProgressStyle::with_template(TEMPLATE)
+            .unwrap()
+            .with_key("avg_rate", |state: &ProgressState, w: &mut dyn Write| {
+                let secs = state.elapsed().as_secs_f64();
+                let rate = if secs > 0. {
+                    state.pos() as f64 / secs
+                } else {
+                    0.
+                };
+
+                let _ = write!(w, "{}/s", HumanBytes(rate as u64));
+            })
+            .progress_chars("=> ");
+
+        Progress {
+            bar: Some(ProgressBar::new(total).with_style(style)),
+        }
+    }
+
+    pub fn hidden() -> Self {
+        Progress { bar: None }
+    }
+
+    pub fn set_position(&self, pos: u64) {
+        if let Some(bar) = &self.bar {
+            bar.set_position(pos);
+        }
+    }
+
+    /// Run `f`, e.g. to log a line, with the bar temporarily hidden
+    pub fn suspend<F: FnOnce()>(&self, f: F) {
+        match &self.bar {
+            Some(bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use sab::config::{Backup, SNAPSHOT_ID_FORMAT};
+use sab::error::{Result, SabError};
+
+use chrono::{DateTime, Utc};
+
+/// Which snapshot of a backup a command works on
+pub enum Snapshot {
+    Latest,
+    Id(String),
+    /// The latest one taken at or before the given time
+    At(DateTime<Utc>),
+}
+
+impl Snapshot {
+    /// Pick the snapshot among the ones of the backup `name`, ordered oldest first
+    pub fn select(&self, name: &str, snapshots: Vec<Backup>) -> Result<Backup> {
+        let found = match self {
+            Snapshot::Latest => snapshots.into_iter().next_back(),
+            Snapshot::Id(id) => snapshots.into_iter().find(|b| &b.snapshot == id),
+            Snapshot::At(at) => {
+                let at = at.format(SNAPSHOT_ID_FORMAT).to_string();
+                snapshots.into_iter().rfind(|b| b.snapshot <= at)
+            }
+        };
+
+        found.ok_or_else(|| match self {
+            Snapshot::Latest => SabError::NotFound(format!("no backup named {}", name)),
+            Snapshot::Id(id) => {
+                SabError::NotFound(format!("backup {} has no snapshot {}", name, id))
+            }
+            Snapshot::At(at) => SabError::NotFound(format!(
+                "backup {} has no snapshot taken at or before {}",
+                name,
+                at.to_rfc3339()
+            )),
+        })
+    }
+}
+
+/// ID of a new snapshot taken now, after the `latest` one
+pub fn new_snapshot_id(latest: Option<&Backup>) -> Result<String> {
+    let id = Utc::now().format(SNAPSHOT_ID_FORMAT).to_string();
+
+    match latest {
+        Some(backup) if backup.snapshot >= id => Err(SabError::State(format!(
+            "snapshot {} of {} already exists",
+            &backup.snapshot,
+            backup.base_key()
+        ))),
+        _ => Ok(id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn snapshots() -> Vec<Backup> {
+        ["20261001T020000Z", "20261008T020000Z", "20261015T020000Z"]
+            .into_iter()
+            .map(|id| Backup {
+                name: format!("p/db@{}", id),
+                snapshot: id.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_latest() {
+        let backup = Snapshot::Latest.select("db", snapshots()).unwrap();
+        assert_eq!(backup.snapshot, "20261015T020000Z");
+    }
+
+    #[test]
+    fn select_by_id() {
+        let id = Snapshot::Id("20261008T020000Z".to_string());
+        assert_eq!(
+            id.select("db", snapshots()).unwrap().snapshot,
+            "20261008T020000Z"
+        );
+
+        let missing = Snapshot::Id("20261009T020000Z".to_string());
+        assert!(matches!(
+            missing.select("db", snapshots()),
+            Err(SabError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn select_at() {
+        let at = Snapshot::At(Utc.with_ymd_and_hms(2026, 10, 14, 23, 59, 59).unwrap());
+        assert_eq!(
+            at.select("db", snapshots()).unwrap().snapshot,
+            "20261008T020000Z"
+        );
+
+        let exact = Snapshot::At(Utc.with_ymd_and_hms(2026, 10, 8, 2, 0, 0).unwrap());
+        assert_eq!(
+            exact.select("db", snapshots()).unwrap().snapshot,
+            "20261008T020000Z"
+        );
+
+        let before = Snapshot::At(Utc.with_ymd_and_hms(2026, 9, 30, 0, 0, 0).unwrap());
+        assert!(matches!(
+            before.select("db", snapshots()),
+            Err(SabError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn select_from_none() {
+        assert!(matches!(
+            Snapshot::Latest.select("db", vec![]),
+            Err(SabError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn new_id_after_latest() {
+        let id = new_snapshot_id(snapshots().last()).unwrap();
+        assert!(id.as_str() > "20261015T020000Z");
+        assert!(new_snapshot_id(None).is_ok());
+    }
+
+    #[test]
+    fn new_id_not_after_latest() {
+        let future = Backup {
+            snapshot: "29991231T235959Z".to_string(),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            new_snapshot_id(Some(&future)),
+            Err(SabError::State(_))
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+use sab::error::{Result, SabError};
+
+use chrono::{DateTime, Utc};
+
+/// Render a backup name template.
+///
+/// `{date}`, `{time}` and `{timestamp}` are replaced with the given time and
+/// `{hostname}` with the host name, any other placeholder has to be one of `vars`.
+/// The name may contain `/` to group backups, e.g. `{hostname}/{basename}-{date}`.
+pub fn render_name(template: &str, vars: &[(&str, &str)], now: DateTime<Utc>) -> Result<String> {
+    render(template, vars, Some(now))
+}
+
+/// Name the runs of a template are recorded under in the metrics. The time placeholders
+/// are kept, so that every run is the same series, e.g. `db.tar-{date}`.
+pub fn series_name(template: &str, vars: &[(&str, &str)]) -> Result<String> {
+    render(template, vars, None)
+}
+
+fn render(template: &str, vars: &[(&str, &str)], now: Option<DateTime<Utc>>) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(|| {
+            SabError::Config(format!(
+                "unclosed placeholder in name template {}",
+                template
+            ))
+        })? + start;
+
+        name.push_str(&rest[..start]);
+
+        let placeholder = &rest[start + 1..end];
+        match (placeholder, now) {
+            ("date" | "time" | "timestamp", None) => name.push_str(&rest[start..=end]),
+            ("date", Some(now)) => name.push_str(&now.format("%Y-%m-%d").to_string()),
+            ("time", Some(now)) => name.push_str(&now.format("%H%M%S").to_string()),
+            ("timestamp", Some(now)) => name.push_str(&now.format("%Y%m%dT%H%M%SZ").to_string()),
+            ("hostname", _) => name.push_str(&gethostname::gethostname().to_string_lossy()),
+            _ => match vars.iter().find(|(var, _)| *var == placeholder) {
+                Some((_, value)) => name.push_str(value),
+                None => {
+                    return Err(SabError::Config(format!(
+                        "unknown placeholder {{{}}} in name template {}",
+                        placeholder, template
+                    )))
+                }
+            },
+        }
+
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+
+    let invalid_segment = |segment: &str| segment.is_empty() || segment == "." || segment == "..";
+    if name.split('/').any(invalid_segment) {
+        return Err(SabError::Config(format!(
+            "invalid backup name {} rendered from template {}",
+            name, template
+        )));
+    }
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 18, 2, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let name = render_name(
+            "{job}/{basename}-{date}-{time}-{timestamp}",
+            &[("job", "db"), ("basename", "db.tar")],
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(name, "db/db.tar-2026-10-18-023000-20261018T023000Z");
+    }
+
+    #[test]
+    fn series_name_keeps_time_placeholders() {
+        let name = series_name("{basename}-{date}T{time}", &[("basename", "db.tar")]).unwrap();
+        assert_eq!(name, "db.tar-{date}T{time}");
+    }
+
+    #[test]
+    fn unknown_placeholder() {
+        let res = render_name("{job}-{nope}", &[("job", "db")], now());
+        assert!(matches!(res, Err(SabError::Config(msg)) if msg.contains("{nope}")));
+    }
+
+    #[test]
+    fn unclosed_placeholder() {
+        let res = render_name("{job", &[("job", "db")], now());
+        assert!(matches!(res, Err(SabError::Config(_))));
+    }
+
+    #[test]
+    fn invalid_segments() {
+        for template in ["/{job}", "{job}/", "a//{job}", "../{job}", "{job}/."] {
+            let res = render_name(template, &[("job", "db")], now());
+            assert!(matches!(res, Err(SabError::Config(_))), "{}", template);
+        }
+    }
+}
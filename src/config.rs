@@ -1,14 +1,50 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
 use std::fs;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions, Permissions, TryLockError};
 use std::io::Write;
-use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use anyhow::Result;
+use crate::error::{Result, ResultExt, SabError};
+use crate::secret;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Utc};
 use expanduser::expanduser;
+use orion::{aead, kdf};
 use serde::{Deserialize, Serialize};
 
+pub const STORAGE_CLASSES: [&str; 8] = [
+    "STANDARD",
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER",
+    "GLACIER_IR",
+    "DEEP_ARCHIVE",
+    "REDUCED_REDUNDANCY",
+];
+
+pub const ARCHIVE_STORAGE_CLASSES: [&str; 2] = ["GLACIER", "DEEP_ARCHIVE"];
+
+pub const DEFAULT_STORAGE_CLASS: &str = "STANDARD";
+
+/// Snapshot IDs are UTC start times, so they sort in the order the snapshots were taken
+pub const SNAPSHOT_ID_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Environment variable holding the master password of an encrypted profiles file
+pub const MASTER_PASSWORD_VAR: &str = "SAB_MASTER_PASSWORD";
+
+/// First line of an encrypted profiles file, followed by the hex encoded salt
+/// and the base64 encoded sealed YAML
+const ENCRYPTED_HEADER: &str = "sab-encrypted-v1";
+
+/// Argon2i parameters for deriving the key from the master password, memory is in KiB
+const KDF_ITERATIONS: u32 = 3;
+const KDF_MEMORY: u32 = 1 << 16;
+
 #[derive(Serialize, Deserialize)]
 pub struct UploadPart {
     pub idx: usize,
@@ -19,7 +55,7 @@ pub struct UploadPart {
     pub processed_sha256: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Backup {
     pub name: String,
     pub prefix: String,
@@ -32,59 +68,604 @@ pub struct Backup {
     pub sha256: String,
     pub compression_enabled: bool,
     pub encryption_enabled: bool,
+    #[serde(default)]
+    pub etag: String,
+    #[serde(default)]
+    pub storage_class: String,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    #[serde(default)]
+    pub lock: ObjectLock,
+    /// Job the backup was made by, see [Job]
+    #[serde(default)]
+    pub job: Option<String>,
+    /// Every upload of the same name is a new snapshot, identified by its start time
+    #[serde(default)]
+    pub snapshot: String,
+}
+
+/// S3 Object Lock settings applied to a backup object
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ObjectLock {
+    /// GOVERNANCE or COMPLIANCE
+    pub mode: Option<String>,
+    /// RFC 3339 timestamp the retention period ends at
+    pub until: Option<String>,
+    pub legal_hold: bool,
+}
+
+/// Shell commands run around backup commands
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Hooks {
+    /// Run before the upload starts, a failure aborts the upload
+    #[serde(default)]
+    pub pre_upload: Option<String>,
+    #[serde(default)]
+    pub post_upload: Option<String>,
+    #[serde(default)]
+    pub post_download: Option<String>,
+    /// Run when an upload or a download fails
+    #[serde(default)]
+    pub on_failure: Option<String>,
+}
+
+/// Where to report completed and failed uploads, downloads and verifications
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Notifications {
+    /// URL receiving a Slack compatible JSON payload
+    #[serde(default)]
+    pub webhook: Option<String>,
+    #[serde(default)]
+    pub email: Option<Email>,
+    /// Don't notify about successful runs
+    #[serde(default)]
+    pub only_failures: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Email {
+    pub smtp_host: String,
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
+    /// tls, starttls or none
+    #[serde(default = "default_smtp_security")]
+    pub security: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Inline or a secret reference, see [crate::secret]
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_security() -> String {
+    "starttls".to_string()
+}
+
+impl Email {
+    pub fn resolve_password(&self) -> Result<Option<String>> {
+        self.password.as_deref().map(secret::resolve).transpose()
+    }
+}
+
+impl Hooks {
+    /// Fill the hooks not set here from `defaults`
+    pub fn with_defaults(&self, defaults: &Hooks) -> Hooks {
+        Hooks {
+            pre_upload: self.pre_upload.clone().or(defaults.pre_upload.clone()),
+            post_upload: self.post_upload.clone().or(defaults.post_upload.clone()),
+            post_download: self
+                .post_download
+                .clone()
+                .or(defaults.post_download.clone()),
+            on_failure: self.on_failure.clone().or(defaults.on_failure.clone()),
+        }
+    }
+}
+
+impl ObjectLock {
+    pub fn is_enabled(&self) -> bool {
+        self.mode.is_some() || self.legal_hold
+    }
+
+    pub fn retain_until(&self) -> Option<DateTime<Utc>> {
+        self.until
+            .as_ref()
+            .and_then(|until| DateTime::parse_from_rfc3339(until).ok())
+            .map(|until| until.with_timezone(&Utc))
+    }
 }
 
 impl Backup {
     pub fn load(path: &Path) -> Result<Self> {
-        let data = fs::read_to_string(path)?;
-        let backup = serde_yaml::from_str(&data)?;
-
-        Ok(backup)
+        load_file(path, |raw| Ok(serde_yaml::from_str(raw)?))
     }
 
     pub fn save(&self, path: &Path) -> Result<()> {
         save(&self, path)
     }
+
+    /// Remove the snapshot state file along with its backup copy, and the backup directory
+    /// once the last snapshot is gone. The lock file is kept, another process may be
+    /// waiting on it and would lock a file nobody else sees once it's removed.
+    pub fn remove(path: &Path) -> Result<()> {
+        fs::remove_file(path)?;
+
+        let bak = backup_copy(path);
+        if bak.exists() {
+            fs::remove_file(bak)?;
+        }
+
+        if let Some(dir) = path.parent() {
+            if fs::read_dir(dir)?.next().is_none() {
+                fs::remove_dir(dir)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Object key shared by all the snapshots of the backup
+    pub fn base_key(&self) -> &str {
+        self.name
+            .strip_suffix(format!("@{}", &self.snapshot).as_str())
+            .unwrap_or(&self.name)
+    }
+
+    /// Locked backups cannot be deleted until the retention expires and the legal hold is removed
+    pub fn is_locked(&self) -> bool {
+        self.lock.legal_hold
+            || self
+                .lock
+                .retain_until()
+                .is_some_and(|until| until > Utc::now())
+    }
+
+    /// Archived backups have to be restored before they can be downloaded
+    pub fn is_archived(&self) -> bool {
+        ARCHIVE_STORAGE_CLASSES.contains(&self.storage_class.as_str())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.parts.iter().map(|part| part.original_size).sum()
+    }
+
+    pub fn processed_size(&self) -> u64 {
+        self.parts.iter().map(|part| part.processed_size).sum()
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Profile {
     pub access_key: String,
+    /// Inline or a secret reference, see [crate::secret]
     pub secret_key: String,
     pub region: String,
     pub bucket: String,
+    /// Hex encoded key, inline or a secret reference
     pub encryption_key: String,
     pub prefix: String,
+    #[serde(default)]
+    pub storage_class: Option<String>,
+    /// Server-side encryption managed by S3: AES256 or aws:kms
+    #[serde(default)]
+    pub sse: Option<String>,
+    #[serde(default)]
+    pub sse_kms_key_id: Option<String>,
+    #[serde(default)]
+    pub sse_bucket_key: bool,
+    /// Hex encoded 256-bit key for server-side encryption with a customer key (SSE-C),
+    /// inline or a secret reference
+    #[serde(default)]
+    pub sse_customer_key: Option<String>,
+    /// Tags applied to every backup uploaded with this profile
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub notify: Notifications,
 }
 
-impl Default for Profile {
-    fn default() -> Self {
-        Profile {
-            access_key: "".to_string(),
-            secret_key: "".to_string(),
-            region: "".to_string(),
-            bucket: "".to_string(),
-            encryption_key: "".to_string(),
-            prefix: "".to_string(),
+impl Profile {
+    pub fn resolve_secret_key(&self) -> Result<String> {
+        secret::resolve(&self.secret_key)
+    }
+
+    /// Decoded encryption key, empty when the profile has none
+    pub fn resolve_encryption_key(&self) -> Result<Vec<u8>> {
+        hex::decode(secret::resolve(&self.encryption_key)?)
+            .or_crypto("failed to hex decode encryption key")
+    }
+
+    /// Decoded SSE-C key
+    pub fn resolve_sse_customer_key(&self) -> Result<Option<Vec<u8>>> {
+        match &self.sse_customer_key {
+            Some(key) => Ok(Some(
+                hex::decode(secret::resolve(key)?)
+                    .or_crypto("failed to hex decode sse customer key")?,
+            )),
+            None => Ok(None),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// What a job backs up
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    File(String),
+    /// Directory archived with tar
+    Dir(String),
+    /// Standard output of a shell command
+    Command(String),
+}
+
+/// Backup job definition from the jobs file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Job {
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub source: Source,
+    #[serde(default = "default_profile")]
+    pub profile: String,
+    #[serde(default)]
+    pub chunk_size: Option<String>,
+    #[serde(default)]
+    pub compression: bool,
+    /// Enabled unless turned off, like for `sab upload`
+    #[serde(default = "default_true")]
+    pub encryption: bool,
+    #[serde(default)]
+    pub storage_class: Option<String>,
+    /// Backup name template, supports {job}, {hostname}, {date}, {time} and {timestamp}
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Number of completed backups of the job to keep, older ones are deleted
+    #[serde(default)]
+    pub retention: Option<usize>,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Hooks not set here fall back to the profile hooks
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Replaces the profile notifications when set
+    #[serde(default)]
+    pub notify: Option<Notifications>,
+    /// Cron expression for `sab daemon`, e.g. "30 2 * * *"
+    #[serde(default)]
+    pub schedule: Option<String>,
+}
+
+fn default_profile() -> String {
+    "default".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Jobs {
+    pub jobs: BTreeMap<String, Job>,
+}
+
+impl Jobs {
+    pub fn load() -> Result<Self> {
+        let raw = fs::read_to_string(Config::jobs_file())?;
+        let jobs: Jobs = serde_yaml::from_str(&raw)?;
+
+        Ok(jobs)
+    }
+}
+
+/// Object key of the snapshot of the backup with the given key
+pub fn snapshot_key(key: &str, snapshot: &str) -> String {
+    format!("{}@{}", key, snapshot)
+}
+
+/// Advisory lock on a backup directory, held until dropped, so that
+/// two sab processes never work on the same backup
+pub struct BackupLock {
+    _file: File,
+}
+
+impl BackupLock {
+    /// Lock the backup directory, failing with the PID of the process holding
+    /// the lock unless `wait` is set, in which case wait for it to be released
+    pub async fn acquire(dir: &Path, name: &str, wait: bool) -> Result<Self> {
+        let path = lock_file(dir);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&path)?;
+
+        let mut waiting = false;
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let pid = fs::read_to_string(&path).unwrap_or_default();
+                    let pid = match pid.trim() {
+                        "" => "unknown".to_string(),
+                        pid => pid.to_string(),
+                    };
+
+                    if !wait {
+                        return Err(SabError::State(format!(
+                            "backup {} is in use by PID {}",
+                            name, pid
+                        )));
+                    }
+
+                    if !waiting {
+                        log::info!("backup {} is in use by PID {}, waiting", name, pid);
+                        waiting = true;
+                    }
+
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(TryLockError::Error(err)) => {
+                    return Err(SabError::Io(format!("failed to lock backup: {}", err)))
+                }
+            }
+        }
+
+        file.set_len(0)?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+
+        Ok(BackupLock { _file: file })
+    }
+}
+
+/// Outcome of the last runs of a job
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct JobStatus {
+    pub last_run: String,
+    #[serde(default)]
+    pub last_success: Option<String>,
+    #[serde(default)]
+    pub last_failure: Option<String>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub last_backup: Option<String>,
+    /// Duration of the last run in seconds
+    #[serde(default)]
+    pub duration: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Status {
+    pub jobs: BTreeMap<String, JobStatus>,
+}
+
+impl Status {
+    /// Load the status file, a missing file is an empty status
+    pub fn load() -> Result<Self> {
+        let path = Config::status_file();
+        if !path.exists() {
+            return Ok(Status::default());
+        }
+
+        load_file(&path, |raw| Ok(serde_yaml::from_str(raw)?))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save(&self, Config::status_file().as_path())
+    }
+
+    /// Change the status file while holding its lock,
+    /// so that concurrent runs don't lose each other's updates
+    pub fn update<F: FnOnce(&mut Status)>(f: F) -> Result<()> {
+        let _lock = lock(&Config::status_file())?;
+
+        let mut status = Status::load()?;
+        f(&mut status);
+        status.save()
+    }
+}
+
+/// Outcome of the upload runs of a backup, exported as Prometheus metrics
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct BackupMetrics {
+    /// Unix timestamps
+    pub last_run: i64,
+    #[serde(default)]
+    pub last_success: Option<i64>,
+    /// Duration of the last run in seconds
+    pub duration: f64,
+    /// Sizes and chunk count of the last successful backup
+    pub original_bytes: u64,
+    pub processed_bytes: u64,
+    pub chunks: u64,
+    /// Totals across all runs
+    pub retries: u64,
+    pub failures: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Metrics {
+    /// Profile name to backup name to metrics
+    pub backups: BTreeMap<String, BTreeMap<String, BackupMetrics>>,
+}
+
+impl Metrics {
+    /// Load the metrics file, a missing file means no runs yet
+    pub fn load() -> Result<Self> {
+        let path = Config::metrics_file();
+        if !path.exists() {
+            return Ok(Metrics::default());
+        }
+
+        load_file(&path, |raw| Ok(serde_yaml::from_str(raw)?))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        save(&self, Config::metrics_file().as_path())
+    }
+
+    /// Change the metrics file while holding its lock, returning the saved metrics
+    pub fn update<F: FnOnce(&mut Metrics)>(f: F) -> Result<Self> {
+        let _lock = lock(&Config::metrics_file())?;
+
+        let mut metrics = Metrics::load()?;
+        f(&mut metrics);
+        metrics.save()?;
+
+        Ok(metrics)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct Config {
     profiles: HashMap<String, Profile>,
+    /// Set when the profiles file is encrypted
+    #[serde(skip)]
+    master_key: Option<MasterKey>,
+}
+
+struct MasterKey {
+    salt: kdf::Salt,
+    key: aead::SecretKey,
+}
+
+impl MasterKey {
+    fn derive(password: &str, salt: kdf::Salt) -> Result<Self> {
+        let password =
+            kdf::Password::from_slice(password.as_bytes()).or_crypto("invalid master password")?;
+        let key = kdf::derive_key(&password, &salt, KDF_ITERATIONS, KDF_MEMORY, 32)
+            .or_crypto("failed to derive key from master password")?;
+        let key = aead::SecretKey::from_slice(key.unprotected_as_bytes())
+            .or_crypto("failed to derive key from master password")?;
+
+        Ok(MasterKey { salt, key })
+    }
 }
 
 impl Config {
+    /// Load the profiles, the master password of an encrypted profiles file
+    /// is taken from [MASTER_PASSWORD_VAR]
     pub fn load() -> Result<Self> {
+        Self::load_with(|| {
+            std::env::var(MASTER_PASSWORD_VAR).map_err(|_| {
+                SabError::Config(format!(
+                    "profiles file is encrypted, set {}",
+                    MASTER_PASSWORD_VAR
+                ))
+            })
+        })
+    }
+
+    /// Load the profiles, calling `password` for the master password
+    /// only if the profiles file is encrypted
+    pub fn load_with<F>(password: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        // No backup copy to fall back to, it could hold the secrets unencrypted
         let raw = fs::read_to_string(Self::profiles_file())?;
-        let config: Config = serde_yaml::from_str(&raw)?;
+        Self::parse(&raw, password)
+    }
+
+    fn parse<F>(raw: &str, password: F) -> Result<Self>
+    where
+        F: FnOnce() -> Result<String>,
+    {
+        let mut lines = raw.lines();
+        if lines.next() != Some(ENCRYPTED_HEADER) {
+            return Ok(serde_yaml::from_str(raw)?);
+        }
+
+        let (salt, sealed) = match (lines.next(), lines.next()) {
+            (Some(salt), Some(sealed)) => (salt, sealed),
+            _ => {
+                return Err(SabError::Config(
+                    "truncated encrypted profiles file".to_string(),
+                ))
+            }
+        };
+
+        let salt = hex::decode(salt).or_config("invalid encrypted profiles file salt")?;
+        let salt =
+            kdf::Salt::from_slice(&salt).or_config("invalid encrypted profiles file salt")?;
+        let sealed = BASE64
+            .decode(sealed)
+            .or_config("invalid encrypted profiles file data")?;
+
+        let master_key = MasterKey::derive(&password()?, salt)?;
+        let raw = aead::open(&master_key.key, &sealed).map_err(|_| {
+            SabError::Crypto(
+                "failed to decrypt profiles file, wrong master password or corrupted file"
+                    .to_string(),
+            )
+        })?;
+
+        let mut config: Config = serde_yaml::from_slice(&raw)?;
+        config.master_key = Some(master_key);
 
         Ok(config)
     }
 
+    /// Save the profiles without a backup copy, removing the one left by older versions,
+    /// so that no copy with the secrets unencrypted stays around after encrypting the file
     pub fn save(&self) -> Result<()> {
-        save(&self, Config::profiles_file().as_path())
+        let path = Config::profiles_file();
+        let data = self.serialize()?;
+        replace_file(path.as_path(), data.as_bytes())?;
+
+        let bak = backup_copy(&path);
+        if bak.exists() {
+            fs::remove_file(bak)?;
+        }
+
+        Ok(())
+    }
+
+    // Profiles file contents, sealed with the master key when it's set
+    fn serialize(&self) -> Result<String> {
+        let data = serde_yaml::to_string(&self)?;
+
+        let master_key = match &self.master_key {
+            Some(master_key) => master_key,
+            None => return Ok(data),
+        };
+
+        let sealed = aead::seal(&master_key.key, data.as_bytes())
+            .or_crypto("failed to encrypt profiles file")?;
+
+        Ok(format!(
+            "{}\n{}\n{}\n",
+            ENCRYPTED_HEADER,
+            hex::encode(master_key.salt.as_ref()),
+            BASE64.encode(sealed)
+        ))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.master_key.is_some()
+    }
+
+    /// Encrypt the profiles file with a key derived from the master password
+    /// from the next save on
+    pub fn encrypt(&mut self, password: &str) -> Result<()> {
+        self.master_key = Some(MasterKey::derive(password, kdf::Salt::default())?);
+        Ok(())
+    }
+
+    /// Store the profiles file as plain YAML from the next save on
+    pub fn decrypt(&mut self) {
+        self.master_key = None;
     }
 
     pub fn profile(&self, name: &str) -> Option<&Profile> {
@@ -95,38 +676,323 @@ impl Config {
         self.profiles.insert(name.to_string(), prof);
     }
 
-    pub fn backup(&self, name: &str) -> PathBuf {
+    pub fn remove_profile(&mut self, name: &str) -> Option<Profile> {
+        self.profiles.remove(name)
+    }
+
+    /// Profile names in alphabetical order
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Directory keeping the snapshots of the backup with the given object key, the key is
+    /// percent-encoded so that the path separators in it are kept in the directory name
+    pub fn backup_dir(&self, key: &str) -> PathBuf {
         Self::sab_dir()
             .join("backups")
-            .join(format!("{}.yml", name))
+            .join(urlencoding::encode(key).as_ref())
+    }
+
+    /// State file of a snapshot of the backup with the given object key
+    pub fn snapshot_file(&self, key: &str, snapshot: &str) -> PathBuf {
+        self.backup_dir(key).join(format!("{}.yml", snapshot))
+    }
+
+    pub fn backup_file(&self, backup: &Backup) -> PathBuf {
+        self.snapshot_file(backup.base_key(), &backup.snapshot)
+    }
+
+    /// Snapshots of the backup with the given object key, oldest first
+    pub fn snapshots(&self, key: &str) -> Result<Vec<Backup>> {
+        let mut snapshots = load_dir(&self.backup_dir(key))?;
+        snapshots.sort_by(|a, b| a.snapshot.cmp(&b.snapshot));
+
+        Ok(snapshots)
+    }
+
+    /// Move the single state file per backup kept by older versions
+    /// to the first snapshot of the backup
+    pub fn migrate_backups(&self) -> Result<()> {
+        let dir = Self::sab_dir().join("backups");
+        if !dir.exists() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            // Snapshot directories of backups named *.yml end in .yml as well
+            if !path.is_file() || path.extension().is_none_or(|ext| ext != "yml") {
+                continue;
+            }
+
+            // A running older version holds the lock, leave it to finish first
+            let lock = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .mode(0o600)
+                .open(lock_file(&path))?;
+            match lock.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    log::warn!("backup state {} is in use, not moving it", path.display());
+                    continue;
+                }
+                Err(TryLockError::Error(err)) => {
+                    return Err(SabError::Io(format!("failed to lock backup: {}", err)))
+                }
+            }
+
+            let mut backup = match Backup::load(path.as_path()) {
+                Ok(backup) => backup,
+                Err(err) => {
+                    log::warn!("failed to load backup state {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            // Older versions store the time in the Display format
+            let started = backup
+                .started
+                .parse::<DateTime<Utc>>()
+                .or_else(|_| path.metadata().and_then(|md| md.modified()).map(Into::into))?;
+            backup.snapshot = started.format(SNAPSHOT_ID_FORMAT).to_string();
+
+            let target = self.backup_file(&backup);
+            log::info!(
+                "moving backup state {} to {}",
+                path.display(),
+                target.display()
+            );
+
+            fs::create_dir_all(self.backup_dir(backup.base_key()))?;
+            backup.save(&target)?;
+
+            for file in [backup_copy(&path), path] {
+                if file.exists() {
+                    fs::remove_file(file)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// All the snapshots of all the backups
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        let dir = Self::sab_dir().join("backups");
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut backups = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                backups.extend(load_dir(&path)?);
+            }
+        }
+
+        Ok(backups)
     }
 
     pub fn sab_dir() -> PathBuf {
-        PathBuf::from(expanduser("~/.sab").unwrap())
+        expanduser("~/.sab").unwrap()
     }
 
     pub fn profiles_file() -> PathBuf {
         Self::sab_dir().join("profiles.yml")
     }
-}
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            profiles: Default::default(),
-        }
+    pub fn jobs_file() -> PathBuf {
+        Self::sab_dir().join("jobs.yml")
+    }
+
+    pub fn status_file() -> PathBuf {
+        Self::sab_dir().join("status.yml")
+    }
+
+    pub fn metrics_file() -> PathBuf {
+        Self::sab_dir().join("metrics.yml")
     }
 }
 
 fn save<T: ?Sized + Serialize>(obj: &T, path: &Path) -> Result<()> {
+    let data = serde_yaml::to_string(obj)?;
+    write_file(path, data.as_bytes())
+}
+
+// The previous version of the file is kept as a backup copy for load_file to fall back to
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    if path.exists() {
+        let bak = backup_copy(path);
+        fs::copy(path, &bak)?;
+        fs::set_permissions(&bak, Permissions::from_mode(0o600))?;
+    }
+
+    replace_file(path, data)
+}
+
+// The file is written to a temporary file first and renamed over the target,
+// so a crash never leaves it partially written
+fn replace_file(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = with_suffix(path, ".tmp");
+
     let mut f = OpenOptions::new()
         .create(true)
         .write(true)
+        .truncate(true)
         .mode(0o600)
-        .open(path)?;
+        .open(&tmp)?;
 
-    let data = serde_yaml::to_string(obj)?;
-    f.write_all(data.as_bytes())?;
+    f.write_all(data)?;
+    f.sync_all()?;
+
+    fs::rename(&tmp, path)?;
+
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
 
     Ok(())
 }
+
+// Parse the file, falling back to its backup copy when it's corrupted
+fn load_file<T, F>(path: &Path, mut parse: F) -> Result<T>
+where
+    F: FnMut(&str) -> Result<T>,
+{
+    let raw = fs::read_to_string(path)?;
+
+    let err = match parse(&raw) {
+        Ok(val) => return Ok(val),
+        Err(err) => err,
+    };
+
+    let bak = backup_copy(path);
+    let raw = match fs::read_to_string(&bak) {
+        Ok(raw) => raw,
+        Err(_) => return Err(err),
+    };
+
+    let val =
+        parse(&raw).map_err(|_| err.context(&format!("failed to parse {}", path.display())))?;
+    log::warn!(
+        "{} is corrupted, loaded the previous version from {}",
+        path.display(),
+        bak.display()
+    );
+
+    Ok(val)
+}
+
+fn load_dir(dir: &Path) -> Result<Vec<Backup>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|ext| ext == "yml") {
+            backups.push(Backup::load(path.as_path())?);
+        }
+    }
+
+    Ok(backups)
+}
+
+// Lock the file for the lifetime of the returned lock file, waiting for other processes
+fn lock(path: &Path) -> Result<File> {
+    let path = lock_file(path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(&path)?;
+
+    file.lock()
+        .or_io(&format!("failed to lock {}", path.display()))?;
+
+    Ok(file)
+}
+
+fn lock_file(path: &Path) -> PathBuf {
+    with_suffix(path, ".lock")
+}
+
+fn backup_copy(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_encryption_enabled_by_default() {
+        let job: Job = serde_yaml::from_str("source:\n  file: /tmp/db.dump\n").unwrap();
+        assert!(job.encryption);
+
+        let job: Job =
+            serde_yaml::from_str("source:\n  file: /tmp/db.dump\nencryption: false\n").unwrap();
+        assert!(!job.encryption);
+    }
+
+    fn encrypted_config(password: &str) -> String {
+        let mut cfg = Config::default();
+        cfg.set_profile(
+            "default",
+            Profile {
+                bucket: "backups".to_string(),
+                ..Default::default()
+            },
+        );
+        cfg.encrypt(password).unwrap();
+        cfg.serialize().unwrap()
+    }
+
+    #[test]
+    fn encrypted_config_round_trip() {
+        let raw = encrypted_config("secret");
+        assert!(raw.starts_with(ENCRYPTED_HEADER));
+        assert!(!raw.contains("backups"));
+
+        let cfg = Config::parse(&raw, || Ok("secret".to_string())).unwrap();
+        assert!(cfg.is_encrypted());
+        assert_eq!(cfg.profile("default").unwrap().bucket, "backups");
+    }
+
+    #[test]
+    fn encrypted_config_wrong_password() {
+        let raw = encrypted_config("secret");
+
+        let res = Config::parse(&raw, || Ok("wrong".to_string()));
+        assert!(matches!(res, Err(SabError::Crypto(_))));
+    }
+
+    #[test]
+    fn plain_config_needs_no_password() {
+        let cfg = Config::parse("profiles: {}\n", || panic!("password asked")).unwrap();
+        assert!(!cfg.is_encrypted());
+    }
+}
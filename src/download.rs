@@ -0,0 +1,241 @@
+use std::io::Read;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::config::Backup;
+use crate::error::{Result, ResultExt, SabError};
+use crate::event::{Event, EventHandler};
+use crate::storage::Storage;
+
+use flate2::read::GzDecoder;
+use orion::aead;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+/// Outcome of a successful download
+#[derive(Debug, Clone)]
+pub struct DownloadSummary {
+    pub chunks: usize,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Verified download of a backup.
+///
+/// Every chunk is checked against the processed checksum from the manifest,
+/// decrypted, decompressed and checked against the original checksum before
+/// it's written out. The whole-file checksum is checked at the end.
+pub struct Downloader<'a, S> {
+    storage: &'a S,
+    backup: &'a Backup,
+    encryption_key: Option<Vec<u8>>,
+    on_event: Option<EventHandler>,
+}
+
+impl<'a, S: Storage> Downloader<'a, S> {
+    pub fn new(storage: &'a S, backup: &'a Backup) -> Self {
+        Downloader {
+            storage,
+            backup,
+            encryption_key: None,
+            on_event: None,
+        }
+    }
+
+    pub fn encryption_key(mut self, key: Option<Vec<u8>>) -> Self {
+        self.encryption_key = key;
+        self
+    }
+
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.on_event = Some(Arc::new(handler));
+        self
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(handler) = &self.on_event {
+            handler(&event);
+        }
+    }
+
+    /// Download the backup, writing the restored data into `out`
+    pub async fn download<W: AsyncWrite + Unpin>(self, out: W) -> Result<DownloadSummary> {
+        if !self.backup.done {
+            return Err(SabError::State(format!(
+                "backup {} is not completed",
+                &self.backup.name
+            )));
+        }
+
+        let reader = self.storage.download(self.backup).await?;
+        let reader = CountingReader {
+            inner: reader,
+            read: 0,
+            total: self.backup.processed_size(),
+            on_event: self.on_event.clone(),
+        };
+
+        self.read(reader, out).await
+    }
+
+    /// Download and check the backup without keeping the restored data
+    pub async fn verify(self) -> Result<DownloadSummary> {
+        self.download(tokio::io::sink()).await
+    }
+
+    async fn read<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+        &self,
+        mut reader: R,
+        mut out: W,
+    ) -> Result<DownloadSummary> {
+        let started = Instant::now();
+        let backup = self.backup;
+
+        self.emit(Event::DownloadStarted {
+            name: backup.name.clone(),
+        });
+
+        let encryption_key = match (&self.encryption_key, backup.encryption_enabled) {
+            (Some(key), true) => {
+                Some(aead::SecretKey::from_slice(key).or_crypto("failed to load encryption key")?)
+            }
+            (None, true) => {
+                return Err(SabError::Crypto(
+                    "backup is encrypted but no encryption key was provided".to_string(),
+                ))
+            }
+            (_, false) => None,
+        };
+
+        let total_size: f64 = backup
+            .parts
+            .iter()
+            .map(|part| part.original_size as f64)
+            .sum();
+
+        let mut downloaded_size: u64 = 0;
+        let mut hasher = Sha256::new();
+
+        for part in backup.parts.iter() {
+            let chunk_started = Instant::now();
+            let mut buf = vec![0u8; part.processed_size as usize];
+
+            reader.read_exact(buf.as_mut_slice()).await.map_err(|e| {
+                SabError::Network(format!("failed to read chunk from the stream: {}", e))
+            })?;
+
+            let processed_hash = hex::encode(Sha256::digest(buf.as_slice()));
+
+            if processed_hash != part.processed_sha256 {
+                return Err(SabError::Integrity(format!(
+                    "chunk {} processed checksum mismatch, expected={}, got={}",
+                    part.idx, &part.processed_sha256, &processed_hash
+                )));
+            }
+
+            if let Some(key) = &encryption_key {
+                buf = aead::open(key, buf.as_slice()).or_crypto("failed to decrypt chunk")?;
+            }
+
+            if backup.compression_enabled {
+                let mut dec = GzDecoder::new(buf.as_slice());
+                let mut dst: Vec<u8> = Vec::new();
+
+                dec.read_to_end(&mut dst).map_err(|e| {
+                    SabError::Integrity(format!("failed to decompress chunk {}: {}", part.idx, e))
+                })?;
+                buf = dst;
+            }
+
+            if buf.len() != part.original_size as usize {
+                return Err(SabError::Integrity(format!(
+                    "chunk {} size mismatch, expected={}, got={}",
+                    part.idx,
+                    part.original_size,
+                    buf.len()
+                )));
+            }
+
+            hasher.update(buf.as_slice());
+
+            let orig_hash = hex::encode(Sha256::digest(buf.as_slice()));
+            if orig_hash != part.original_sha256 {
+                return Err(SabError::Integrity(format!(
+                    "chunk {} checksum mismatch, expected={}, got={}",
+                    part.idx, &part.original_sha256, &orig_hash
+                )));
+            }
+
+            out.write_all(buf.as_slice())
+                .await
+                .or_io("failed to write part to file")?;
+
+            downloaded_size += buf.len() as u64;
+
+            self.emit(Event::ChunkDownloaded {
+                idx: part.idx,
+                size: part.original_size,
+                duration: chunk_started.elapsed(),
+                progress: downloaded_size as f64 / total_size * 100.,
+            });
+        }
+
+        out.flush().await.or_io("failed to flush output")?;
+
+        let hash = hex::encode(hasher.finalize());
+        if hash != backup.sha256 {
+            return Err(SabError::Integrity(format!(
+                "backup checksum mismatch, expected={}, got={}",
+                &backup.sha256, &hash
+            )));
+        }
+
+        self.emit(Event::DownloadCompleted {
+            name: backup.name.clone(),
+            size: downloaded_size,
+            sha256: hash.clone(),
+            duration: started.elapsed(),
+        });
+
+        Ok(DownloadSummary {
+            chunks: backup.parts.len(),
+            size: downloaded_size,
+            sha256: hash,
+        })
+    }
+}
+
+/// Reports the number of bytes read from the backup stream
+struct CountingReader<R> {
+    inner: R,
+    read: u64,
+    total: u64,
+    on_event: Option<EventHandler>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        let read = (buf.filled().len() - before) as u64;
+        if read > 0 {
+            self.read += read;
+
+            if let Some(handler) = &self.on_event {
+                handler(&Event::Progress {
+                    bytes: self.read,
+                    total: Some(self.total),
+                });
+            }
+        }
+
+        res
+    }
+}
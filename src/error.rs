@@ -0,0 +1,201 @@
+use std::fmt::Display;
+use std::process::ExitCode;
+
+use aws_sdk_s3::types::{DisplayErrorContext, SdkError};
+use aws_smithy_types::retry::ProvideErrorKind;
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, SabError>;
+
+/// All the ways a sab command can fail.
+///
+/// Every variant maps to its own process exit code, see [SabError::exit_code].
+#[derive(Debug, Error)]
+pub enum SabError {
+    /// Generic S3 error that doesn't fit any other category (exit code 1)
+    #[error("{0}")]
+    Storage(String),
+
+    /// Invalid or missing configuration, profile or argument (exit code 3)
+    #[error("{0}")]
+    Config(String),
+
+    /// S3 rejected the credentials or denied access (exit code 4)
+    #[error("{0}")]
+    Auth(String),
+
+    /// S3 could not be reached or the connection broke (exit code 5)
+    #[error("{0}")]
+    Network(String),
+
+    /// A backup, object or bucket does not exist (exit code 6)
+    #[error("{0}")]
+    NotFound(String),
+
+    /// Checksum or size mismatch (exit code 7)
+    #[error("{0}")]
+    Integrity(String),
+
+    /// Encryption key, encryption or decryption failure (exit code 8)
+    #[error("{0}")]
+    Crypto(String),
+
+    /// Local file system error (exit code 9)
+    #[error("{0}")]
+    Io(String),
+
+    /// The backup is in a state that doesn't allow the operation,
+    /// e.g. it's not completed or it's locked (exit code 10)
+    #[error("{0}")]
+    State(String),
+
+    /// The backup is archived and has to be restored first (exit code 11)
+    #[error("{0}")]
+    Archived(String),
+
+    /// A pre or post hook command failed (exit code 12)
+    #[error("{0}")]
+    Hook(String),
+}
+
+impl SabError {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            SabError::Storage(_) => 1,
+            SabError::Config(_) => 3,
+            SabError::Auth(_) => 4,
+            SabError::Network(_) => 5,
+            SabError::NotFound(_) => 6,
+            SabError::Integrity(_) => 7,
+            SabError::Crypto(_) => 8,
+            SabError::Io(_) => 9,
+            SabError::State(_) => 10,
+            SabError::Archived(_) => 11,
+            SabError::Hook(_) => 12,
+        }
+    }
+
+    /// Errors worth retrying the failed operation for
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SabError::Network(_) | SabError::Storage(_))
+    }
+
+    /// Short name of the error class
+    pub fn class(&self) -> &'static str {
+        match self {
+            SabError::Storage(_) => "storage",
+            SabError::Config(_) => "config",
+            SabError::Auth(_) => "auth",
+            SabError::Network(_) => "network",
+            SabError::NotFound(_) => "not_found",
+            SabError::Integrity(_) => "integrity",
+            SabError::Crypto(_) => "crypto",
+            SabError::Io(_) => "io",
+            SabError::State(_) => "state",
+            SabError::Archived(_) => "archived",
+            SabError::Hook(_) => "hook",
+        }
+    }
+
+    /// Prefix the error message, keeping the error class
+    pub fn context(self, msg: &str) -> Self {
+        let wrap = |err: String| format!("{}: {}", msg, err);
+
+        match self {
+            SabError::Storage(err) => SabError::Storage(wrap(err)),
+            SabError::Config(err) => SabError::Config(wrap(err)),
+            SabError::Auth(err) => SabError::Auth(wrap(err)),
+            SabError::Network(err) => SabError::Network(wrap(err)),
+            SabError::NotFound(err) => SabError::NotFound(wrap(err)),
+            SabError::Integrity(err) => SabError::Integrity(wrap(err)),
+            SabError::Crypto(err) => SabError::Crypto(wrap(err)),
+            SabError::Io(err) => SabError::Io(wrap(err)),
+            SabError::State(err) => SabError::State(wrap(err)),
+            SabError::Archived(err) => SabError::Archived(wrap(err)),
+            SabError::Hook(err) => SabError::Hook(wrap(err)),
+        }
+    }
+}
+
+/// Classify an S3 SDK error and prefix it with a message, for use with `map_err`
+pub fn sdk_error<E>(msg: &'static str) -> impl FnOnce(SdkError<E>) -> SabError
+where
+    E: ProvideErrorKind + std::error::Error + 'static,
+{
+    move |err| SabError::from(err).context(msg)
+}
+
+impl From<std::io::Error> for SabError {
+    fn from(err: std::io::Error) -> Self {
+        SabError::Io(err.to_string())
+    }
+}
+
+impl From<serde_yaml::Error> for SabError {
+    fn from(err: serde_yaml::Error) -> Self {
+        SabError::Config(err.to_string())
+    }
+}
+
+impl<E> From<SdkError<E>> for SabError
+where
+    E: ProvideErrorKind + std::error::Error + 'static,
+{
+    fn from(err: SdkError<E>) -> Self {
+        let msg = DisplayErrorContext(&err).to_string();
+
+        match &err {
+            SdkError::TimeoutError(_)
+            | SdkError::DispatchFailure(_)
+            | SdkError::ResponseError(_) => SabError::Network(msg),
+            SdkError::ConstructionFailure(_) => SabError::Config(msg),
+            SdkError::ServiceError(e) => {
+                let status = e.raw().http().status().as_u16();
+
+                match (e.err().code(), status) {
+                    (Some("InvalidObjectState"), _) => SabError::Archived(msg),
+                    (
+                        Some(
+                            "AccessDenied"
+                            | "InvalidAccessKeyId"
+                            | "SignatureDoesNotMatch"
+                            | "ExpiredToken",
+                        ),
+                        _,
+                    )
+                    | (_, 401 | 403) => SabError::Auth(msg),
+                    (Some("NoSuchKey" | "NoSuchBucket" | "NoSuchUpload"), _) | (_, 404) => {
+                        SabError::NotFound(msg)
+                    }
+                    _ => SabError::Storage(msg),
+                }
+            }
+            _ => SabError::Storage(msg),
+        }
+    }
+}
+
+/// Attach a context message to foreign errors while classifying them
+pub trait ResultExt<T> {
+    fn or_config(self, msg: &str) -> Result<T>;
+    fn or_crypto(self, msg: &str) -> Result<T>;
+    fn or_io(self, msg: &str) -> Result<T>;
+}
+
+impl<T, E: Display> ResultExt<T> for std::result::Result<T, E> {
+    fn or_config(self, msg: &str) -> Result<T> {
+        self.map_err(|err| SabError::Config(format!("{}: {}", msg, err)))
+    }
+
+    fn or_crypto(self, msg: &str) -> Result<T> {
+        self.map_err(|err| SabError::Crypto(format!("{}: {}", msg, err)))
+    }
+
+    fn or_io(self, msg: &str) -> Result<T> {
+        self.map_err(|err| SabError::Io(format!("{}: {}", msg, err)))
+    }
+}
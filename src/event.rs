@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+/// Progress events reported by [Uploader](crate::upload::Uploader)
+/// and [Downloader](crate::download::Downloader)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    UploadStarted {
+        name: String,
+        resumed: bool,
+    },
+    ChunkSkipped {
+        idx: usize,
+    },
+    /// Number of bytes transferred so far, reported while the data is being sent or received.
+    /// Uploads count input bytes, downloads count bytes read from the backup object.
+    Progress {
+        bytes: u64,
+        total: Option<u64>,
+    },
+    ChunkUploaded {
+        idx: usize,
+        original_size: u64,
+        processed_size: u64,
+        original_sha256: String,
+        processed_sha256: String,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+        /// Percentage of the input uploaded so far, if the input size is known
+        progress: Option<f64>,
+    },
+    /// A chunk upload failed with a transient error and is going to be retried
+    Retry {
+        idx: usize,
+        attempt: usize,
+        error: String,
+    },
+    UploadCompleted {
+        name: String,
+        size: u64,
+        sha256: String,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+    },
+    DownloadStarted {
+        name: String,
+    },
+    ChunkDownloaded {
+        idx: usize,
+        size: u64,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+        progress: f64,
+    },
+    DownloadCompleted {
+        name: String,
+        size: u64,
+        sha256: String,
+        #[serde(serialize_with = "as_secs")]
+        duration: Duration,
+    },
+}
+
+fn as_secs<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64())
+}
+
+pub type EventHandler = Arc<dyn Fn(&Event) + Send + Sync>;
@@ -0,0 +1,38 @@
+//! Chunked, encrypted and resumable backups to S3.
+//!
+//! ```no_run
+//! use sab::config::Config;
+//! use sab::s3::S3Client;
+//! use sab::upload::Uploader;
+//! use sab::download::Downloader;
+//!
+//! # async fn example() -> sab::error::Result<()> {
+//! let cfg = Config::load()?;
+//! let profile = cfg.profile("default").unwrap();
+//! let storage = S3Client::new(profile).await?;
+//! let key = profile.resolve_encryption_key()?;
+//!
+//! let input = tokio::fs::File::open("backup.tar").await?;
+//! let backup = Uploader::new(&storage, input, "laptop/backup.tar")
+//!     .encryption_key(Some(key.clone()))
+//!     .on_event(|event| println!("{:?}", event))
+//!     .upload()
+//!     .await?;
+//!
+//! let output = tokio::fs::File::create("restored.tar").await?;
+//! Downloader::new(&storage, &backup)
+//!     .encryption_key(Some(key))
+//!     .download(output)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod config;
+pub mod download;
+pub mod error;
+pub mod event;
+pub mod s3;
+pub mod secret;
+pub mod storage;
+pub mod upload;
@@ -1,25 +1,38 @@
 extern crate core;
 extern crate log;
 
-mod cmd_download;
-mod cmd_gen_key;
-mod cmd_init;
-mod cmd_list;
-mod cmd_upload;
-mod config;
-mod s3;
-
-use config::Config;
-use s3::S3Client;
-
-use cmd_download::cmd_download;
-use cmd_gen_key::cmd_gen_key;
-use cmd_init::cmd_init;
-use cmd_list::cmd_list;
-use cmd_upload::cmd_upload;
-
-use aws_sdk_s3::model::StorageClass;
-use clap::{ArgAction, Parser, Subcommand};
+mod cli;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use sab::config::{Config, ObjectLock, Profile, DEFAULT_STORAGE_CLASS, STORAGE_CLASSES};
+use sab::error::{Result, ResultExt, SabError};
+use sab::s3::S3Client;
+
+use cli::cmd_config::{cmd_config_decrypt, cmd_config_encrypt, master_password};
+use cli::cmd_daemon::cmd_daemon;
+use cli::cmd_delete::cmd_delete;
+use cli::cmd_doctor::cmd_doctor;
+use cli::cmd_download::cmd_download;
+use cli::cmd_gen_key::cmd_gen_key;
+use cli::cmd_init::{cmd_init, InitValues};
+use cli::cmd_list::{cmd_list, cmd_snapshots};
+use cli::cmd_profile::{
+    cmd_profile_copy, cmd_profile_delete, cmd_profile_edit, cmd_profile_list, cmd_profile_rename,
+    cmd_profile_show,
+};
+use cli::cmd_restore::{cmd_restore_request, cmd_restore_status};
+use cli::cmd_run::cmd_run;
+use cli::cmd_upload::cmd_upload;
+use cli::cmd_verify::cmd_verify;
+use cli::metrics::Recorder;
+use cli::output::{Output, OutputFormat};
+use cli::snapshot::Snapshot;
+
+use aws_sdk_s3::model::{StorageClass, Tier};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand};
 use humanize_rs::bytes::Bytes;
 
 #[derive(Parser)]
@@ -33,6 +46,29 @@ pub struct Cli {
     )]
     profile: String,
 
+    #[arg(
+        long = "output",
+        global = true,
+        value_enum,
+        default_value = "text",
+        help = "Output format, json emits one event per line"
+    )]
+    output: OutputFormat,
+
+    #[arg(
+        long = "output-fd",
+        global = true,
+        help = "Write json events to this file descriptor instead of stderr"
+    )]
+    output_fd: Option<i32>,
+
+    #[arg(
+        long = "metrics-file",
+        global = true,
+        help = "Write Prometheus metrics to this textfile collector file after every backup"
+    )]
+    metrics_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -40,15 +76,78 @@ pub struct Cli {
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Init a new profile")]
-    Init { name: Option<String> },
+    Init {
+        name: Option<String>,
+
+        #[arg(long = "access-key", env = "SAB_ACCESS_KEY")]
+        access_key: Option<String>,
+
+        #[arg(
+            long = "secret-key",
+            env = "SAB_SECRET_KEY",
+            hide_env_values = true,
+            help = "Prefer SAB_SECRET_KEY, options are visible to other users"
+        )]
+        secret_key: Option<String>,
+
+        #[arg(long = "region", env = "SAB_REGION")]
+        region: Option<String>,
+
+        #[arg(long = "bucket", env = "SAB_BUCKET")]
+        bucket: Option<String>,
+
+        #[arg(long = "prefix", env = "SAB_PREFIX")]
+        prefix: Option<String>,
+
+        #[arg(long = "storage-class", env = "SAB_STORAGE_CLASS",
+              value_parser = STORAGE_CLASSES)]
+        storage_class: Option<String>,
+
+        #[arg(long = "sse", env = "SAB_SSE",
+              value_parser = ["none", "AES256", "aws:kms", "SSE-C"])]
+        sse: Option<String>,
+
+        #[arg(long = "sse-kms-key-id", env = "SAB_SSE_KMS_KEY_ID")]
+        sse_kms_key_id: Option<String>,
+
+        #[arg(
+            long = "encryption-key-file",
+            env = "SAB_ENCRYPTION_KEY_FILE",
+            conflicts_with = "no_encryption",
+            help = "File with a hex encoded encryption key, as printed by gen-key"
+        )]
+        encryption_key_file: Option<PathBuf>,
+
+        #[arg(
+            long = "no-encryption",
+            env = "SAB_NO_ENCRYPTION",
+            help = "Don't generate an encryption key"
+        )]
+        no_encryption: bool,
+
+        #[arg(
+            long = "skip-check",
+            env = "SAB_SKIP_CHECK",
+            help = "Save the profile without checking the bucket access"
+        )]
+        skip_check: bool,
+    },
     #[command(about = "List uploads")]
     List {},
     #[command(about = "Generate an encryption key")]
     GenKey {},
     #[command(about = "Create new or resume existing upload")]
+    #[command(group(ArgGroup::new("lock_period").args(["lock_until", "lock_days"])))]
     Upload {
         file: String,
 
+        #[arg(
+            short = 'n',
+            long = "name",
+            help = "Backup name template, supports {basename}, {hostname}, {date}, {time} and {timestamp}, defaults to {basename}"
+        )]
+        name: Option<String>,
+
         #[arg(short = 's', long = "chunk-size", default_value = "100MB")]
         chunk_size: String,
 
@@ -58,85 +157,569 @@ pub enum Commands {
         #[arg(short='e', long="with-encryption", action=ArgAction::Set, default_value_t=true)]
         encryption_enabled: bool,
 
-        #[arg(short='l', long="storage-class", default_value="STANDARD",
-              value_parser=["STANDARD", "DEEP_ARCHIVE"])]
-        storage_class: String,
+        #[arg(short='l', long="storage-class", value_parser=STORAGE_CLASSES,
+              help="Storage class, defaults to the profile storage class or STANDARD")]
+        storage_class: Option<String>,
+
+        #[arg(short='t', long="tag", value_parser=parse_key_value, action=ArgAction::Append,
+              help="Object tag in key=value form, can be repeated")]
+        tags: Vec<(String, String)>,
+
+        #[arg(short='m', long="meta", value_parser=parse_key_value, action=ArgAction::Append,
+              help="Object metadata in key=value form, can be repeated")]
+        metadata: Vec<(String, String)>,
+
+        #[arg(long="lock-mode", value_parser=["GOVERNANCE", "COMPLIANCE"], requires="lock_period",
+              help="Object Lock retention mode")]
+        lock_mode: Option<String>,
+
+        #[arg(long="lock-until", value_parser=parse_date, requires="lock_mode",
+              help="Keep the backup locked until the given date, e.g. 2027-01-01")]
+        lock_until: Option<DateTime<Utc>>,
+
+        #[arg(
+            long = "lock-days",
+            requires = "lock_mode",
+            help = "Keep the backup locked for N days"
+        )]
+        lock_days: Option<i64>,
+
+        #[arg(long = "legal-hold", help = "Place a legal hold on the backup")]
+        legal_hold: bool,
+
+        #[arg(
+            short = 'w',
+            long = "wait",
+            help = "Wait for another sab process using the backup to finish"
+        )]
+        wait: bool,
     },
     #[command(about = "Download a file from the archive")]
     Download {
         name: String,
         output_file: Option<String>,
+
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+
+        #[arg(
+            short = 'w',
+            long = "wait",
+            help = "Wait for an archived backup to be restored"
+        )]
+        wait: bool,
+    },
+    #[command(about = "Request a restore of an archived backup")]
+    RestoreRequest {
+        name: String,
+
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+
+        #[arg(
+            short = 'd',
+            long = "days",
+            default_value_t = 7,
+            help = "Number of days to keep the restored copy"
+        )]
+        days: i32,
+
+        #[arg(short='t', long="tier", default_value="Standard",
+              value_parser=["Bulk", "Standard", "Expedited"])]
+        tier: String,
+    },
+    #[command(about = "Show the restore status of an archived backup")]
+    RestoreStatus {
+        name: String,
+
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+    },
+    #[command(about = "Verify a backup without writing it to disk")]
+    Verify {
+        name: String,
+
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+
+        #[arg(
+            short = 'q',
+            long = "quick",
+            help = "Only compare the remote object size and etag"
+        )]
+        quick: bool,
+    },
+    #[command(about = "Delete a backup snapshot from the archive")]
+    Delete {
+        name: String,
+
+        #[command(flatten)]
+        snapshot: SnapshotArgs,
+
+        #[arg(short = 'y', long = "yes", help = "Do not ask for confirmation")]
+        yes: bool,
+
+        #[arg(
+            short = 'w',
+            long = "wait",
+            help = "Wait for another sab process using the backup to finish"
+        )]
+        wait: bool,
+    },
+    #[command(about = "List the snapshots of a backup")]
+    Snapshots { name: String },
+    #[command(about = "Run backup jobs from the jobs file")]
+    #[command(group(ArgGroup::new("jobs").required(true).args(["job", "all"])))]
+    Run {
+        job: Option<String>,
+
+        #[arg(short = 'a', long = "all", help = "Run all the jobs")]
+        all: bool,
+    },
+    #[command(about = "Run the scheduled jobs from the jobs file")]
+    Daemon {
+        #[arg(
+            long = "metrics-listen",
+            help = "Serve Prometheus metrics on this address, e.g. 127.0.0.1:9898"
+        )]
+        metrics_listen: Option<String>,
+    },
+    #[command(about = "Check the profile credentials, bucket and permissions")]
+    Doctor { profile: Option<String> },
+    #[command(about = "Manage profiles")]
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
     },
+    #[command(about = "Manage the profiles file")]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Args)]
+pub struct SnapshotArgs {
+    #[arg(
+        long = "snapshot",
+        default_value = "latest",
+        help = "Snapshot ID as listed by `sab snapshots`, or latest"
+    )]
+    snapshot: String,
+
+    #[arg(long = "at", value_parser = parse_snapshot_time, conflicts_with = "snapshot",
+          help = "Use the latest snapshot taken at or before the given date, e.g. 2026-10-01")]
+    at: Option<DateTime<Utc>>,
+}
+
+impl SnapshotArgs {
+    fn selector(self) -> Snapshot {
+        match (self.at, self.snapshot.as_str()) {
+            (Some(at), _) => Snapshot::At(at),
+            (None, "latest") => Snapshot::Latest,
+            (None, id) => Snapshot::Id(id.to_string()),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommands {
+    #[command(about = "List profiles")]
+    List {},
+    #[command(about = "Show a profile with the secrets masked")]
+    Show { name: Option<String> },
+    #[command(about = "Change a profile")]
+    Edit { name: Option<String> },
+    #[command(about = "Delete a profile")]
+    Delete {
+        name: String,
+
+        #[arg(short = 'y', long = "yes", help = "Do not ask for confirmation")]
+        yes: bool,
+    },
+    #[command(about = "Rename a profile")]
+    Rename { from: String, to: String },
+    #[command(about = "Copy a profile")]
+    Copy { from: String, to: String },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    #[command(about = "Encrypt the profiles file with a master password")]
+    Encrypt {},
+    #[command(about = "Store the profiles file unencrypted")]
+    Decrypt {},
+}
+
+impl Commands {
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Init { .. } => "init",
+            Commands::List { .. } => "list",
+            Commands::GenKey { .. } => "gen-key",
+            Commands::Upload { .. } => "upload",
+            Commands::Download { .. } => "download",
+            Commands::RestoreRequest { .. } => "restore-request",
+            Commands::RestoreStatus { .. } => "restore-status",
+            Commands::Verify { .. } => "verify",
+            Commands::Delete { .. } => "delete",
+            Commands::Snapshots { .. } => "snapshots",
+            Commands::Run { .. } => "run",
+            Commands::Daemon { .. } => "daemon",
+            Commands::Profile { .. } => "profile",
+            Commands::Doctor { .. } => "doctor",
+            Commands::Config { .. } => "config",
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let output = match Output::new(cli.command.name(), cli.output, cli.output_fd) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("{}", err);
+            return err.exit_code();
+        }
+    };
+
+    // Keep log lines from interleaving with json events unless asked for
+    let default_filter = match (output.is_json(), cli.output_fd) {
+        (true, None) => "off",
+        _ => "info",
+    };
     env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, default_filter),
     );
 
-    let cli = Cli::parse();
+    match run(cli, &output).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            log::error!("{}", err);
+            output.error(&err);
+            err.exit_code()
+        }
+    }
+}
 
+async fn run(cli: Cli, output: &Output) -> Result<()> {
     match cli.command {
         Commands::GenKey {} => {
             cmd_gen_key();
+            Ok(())
         }
-        Commands::Init { name } => {
+        Commands::Init {
+            name,
+            access_key,
+            secret_key,
+            region,
+            bucket,
+            prefix,
+            storage_class,
+            sse,
+            sse_kms_key_id,
+            encryption_key_file,
+            no_encryption,
+            skip_check,
+        } => {
             let name = name.unwrap_or("default".to_string());
-            cmd_init(&name);
+            let values = InitValues {
+                access_key,
+                secret_key,
+                region,
+                bucket,
+                prefix,
+                storage_class,
+                sse,
+                sse_kms_key_id,
+                encryption_key_file,
+                no_encryption,
+                skip_check,
+            };
+
+            cmd_init(&name, &values).await
         }
         Commands::List {} => {
-            let cfg = load_config();
-            let profile = cfg.profile(&cli.profile).expect("unknown profile");
-            let cl = S3Client::new(profile).await;
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
 
-            cmd_list(cl).await;
+            cmd_list(cl, &cfg).await
         }
         Commands::Upload {
             file,
+            name,
             chunk_size,
             compression_enabled,
             encryption_enabled,
             storage_class,
+            tags,
+            metadata,
+            lock_mode,
+            lock_until,
+            lock_days,
+            legal_hold,
+            wait,
         } => {
-            let cfg = load_config();
-            let profile = cfg.profile(&cli.profile).expect("unknown profile");
-            let cl = S3Client::new(profile).await;
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
             let size = chunk_size
                 .parse::<Bytes>()
-                .expect("failed to parse chunk size");
+                .or_config("failed to parse chunk size")?;
 
-            let enc_key =
-                hex::decode(&profile.encryption_key).expect("failed to hex decode encryption key");
+            // Only resolve the key when it's going to be used
+            let enc_key = match encryption_enabled {
+                true => profile.resolve_encryption_key()?,
+                false => vec![],
+            };
 
+            let storage_class = storage_class
+                .or_else(|| profile.storage_class.clone())
+                .unwrap_or_else(|| DEFAULT_STORAGE_CLASS.to_string());
             let class = StorageClass::from(storage_class.as_str());
+
+            let mut all_tags = profile.tags.clone();
+            all_tags.extend(tags);
+
+            let lock_until =
+                lock_until.or_else(|| lock_days.map(|d| Utc::now() + Duration::days(d)));
+            let lock = ObjectLock {
+                mode: lock_mode,
+                until: lock_until.map(|until| until.to_rfc3339()),
+                legal_hold,
+            };
+
             cmd_upload(
                 cl,
                 &file,
+                name.as_deref(),
                 size.size(),
                 compression_enabled,
                 encryption_enabled,
                 enc_key,
                 profile.prefix.to_string(),
                 class,
+                all_tags,
+                metadata.into_iter().collect(),
+                lock,
+                wait,
+                &profile.hooks,
+                &profile.notify,
                 &cfg,
+                output,
+                &Recorder::new(&cli.profile, cli.metrics_file.as_deref()),
             )
-            .await;
+            .await
         }
-        Commands::Download { name, output_file } => {
-            let cfg = load_config();
-            let profile = cfg.profile(&cli.profile).expect("unknown profile");
-            let cl = S3Client::new(profile).await;
+        Commands::Download {
+            name,
+            output_file,
+            snapshot,
+            wait,
+        } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
 
-            let enc_key =
-                hex::decode(&profile.encryption_key).expect("failed to hex decode encryption key");
+            let enc_key = profile.resolve_encryption_key()?;
 
-            let out = output_file.unwrap_or(name.to_string());
-            cmd_download(cl, &name, &out, enc_key, &cfg).await;
+            // Names may group backups with '/', the file is named after the last part
+            let out = output_file.unwrap_or_else(|| name.rsplit('/').next().unwrap().to_string());
+            cmd_download(
+                cl,
+                &name,
+                &snapshot.selector(),
+                &out,
+                enc_key,
+                wait,
+                &profile.hooks,
+                &profile.notify,
+                &cfg,
+                output,
+            )
+            .await
+        }
+        Commands::RestoreRequest {
+            name,
+            snapshot,
+            days,
+            tier,
+        } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
+
+            cmd_restore_request(
+                cl,
+                &name,
+                &snapshot.selector(),
+                days,
+                Tier::from(tier.as_str()),
+                &cfg,
+            )
+            .await
+        }
+        Commands::RestoreStatus { name, snapshot } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
+
+            cmd_restore_status(cl, &name, &snapshot.selector(), &cfg).await
+        }
+        Commands::Verify {
+            name,
+            snapshot,
+            quick,
+        } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
+
+            let enc_key = profile.resolve_encryption_key()?;
+
+            cmd_verify(
+                cl,
+                &name,
+                &snapshot.selector(),
+                quick,
+                enc_key,
+                &profile.notify,
+                &cfg,
+                output,
+            )
+            .await
         }
+        Commands::Delete {
+            name,
+            snapshot,
+            yes,
+            wait,
+        } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
+
+            cmd_delete(cl, &name, &snapshot.selector(), yes, wait, &cfg).await
+        }
+        Commands::Snapshots { name } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &cli.profile)?;
+            let cl = S3Client::new(profile).await?;
+
+            cmd_snapshots(cl, &name, &cfg)
+        }
+        Commands::Run { job, .. } => {
+            let cfg = load_config()?;
+
+            cmd_run(job.as_deref(), &cfg, output, cli.metrics_file.as_deref()).await
+        }
+        Commands::Daemon { metrics_listen } => {
+            let cfg = load_config()?;
+
+            cmd_daemon(&cfg, output, cli.metrics_file.as_deref(), metrics_listen).await
+        }
+        Commands::Doctor { profile } => {
+            let cfg = load_config()?;
+            let profile = load_profile(&cfg, &profile.unwrap_or(cli.profile))?;
+
+            cmd_doctor(profile).await
+        }
+        Commands::Profile { command } => {
+            let cfg = load_config()?;
+
+            match command {
+                ProfileCommands::List {} => cmd_profile_list(&cfg),
+                ProfileCommands::Show { name } => {
+                    cmd_profile_show(&name.unwrap_or(cli.profile), &cfg)
+                }
+                ProfileCommands::Edit { name } => {
+                    cmd_profile_edit(&name.unwrap_or(cli.profile), cfg)
+                }
+                ProfileCommands::Delete { name, yes } => cmd_profile_delete(&name, yes, cfg),
+                ProfileCommands::Rename { from, to } => cmd_profile_rename(&from, &to, cfg),
+                ProfileCommands::Copy { from, to } => cmd_profile_copy(&from, &to, cfg),
+            }
+        }
+        Commands::Config { command } => {
+            let cfg = load_config()?;
+
+            match command {
+                ConfigCommands::Encrypt {} => cmd_config_encrypt(cfg),
+                ConfigCommands::Decrypt {} => cmd_config_decrypt(cfg),
+            }
+        }
+    }
+}
+
+fn parse_key_value(val: &str) -> std::result::Result<(String, String), String> {
+    match val.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("expected key=value, got {}", val)),
+    }
+}
+
+fn parse_date(val: &str) -> std::result::Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(val, "%Y-%m-%d") {
+        return Ok(DateTime::from_utc(date.and_hms_opt(0, 0, 0).unwrap(), Utc));
+    }
+
+    DateTime::parse_from_rfc3339(val)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| format!("expected YYYY-MM-DD or RFC 3339 date, got {}", val))
+}
+
+// A date without the time means the end of the day
+fn parse_snapshot_time(val: &str) -> std::result::Result<DateTime<Utc>, String> {
+    match NaiveDate::parse_from_str(val, "%Y-%m-%d") {
+        Ok(date) => Ok(DateTime::from_utc(
+            date.and_hms_opt(23, 59, 59).unwrap(),
+            Utc,
+        )),
+        Err(_) => parse_date(val),
     }
 }
 
-fn load_config() -> Config {
-    Config::load().expect("failed to load config")
+fn load_config() -> Result<Config> {
+    let cfg = Config::load_with(master_password)
+        .map_err(|e| SabError::Config(format!("failed to load config: {}", e)))?;
+
+    cfg.migrate_backups()
+        .map_err(|e| e.context("failed to migrate backup states"))?;
+
+    Ok(cfg)
+}
+
+fn load_profile<'a>(cfg: &'a Config, name: &str) -> Result<&'a Profile> {
+    cfg.profile(name)
+        .ok_or_else(|| SabError::Config(format!("unknown profile {}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn snapshot_time_date_is_end_of_day() {
+        assert_eq!(
+            parse_snapshot_time("2026-10-01").unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 1, 23, 59, 59).unwrap()
+        );
+    }
+
+    #[test]
+    fn snapshot_time_rfc3339() {
+        assert_eq!(
+            parse_snapshot_time("2026-10-01T12:30:00+02:00").unwrap(),
+            Utc.with_ymd_and_hms(2026, 10, 1, 10, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn snapshot_time_invalid() {
+        assert!(parse_snapshot_time("yesterday").is_err());
+    }
 }
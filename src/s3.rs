@@ -1,20 +1,53 @@
-use crate::config::{Backup, Profile};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
-use anyhow::Result;
-use aws_sdk_s3::model::{CompletedMultipartUpload, CompletedPart, StorageClass};
-use aws_sdk_s3::output::CreateMultipartUploadOutput;
-use aws_sdk_s3::types::ByteStream;
+use crate::config::{Backup, Profile, DEFAULT_STORAGE_CLASS};
+use crate::error::{sdk_error, Result, SabError};
+use crate::storage::{ProgressFn, Storage};
+
+use aws_sdk_s3::model::{
+    CompletedMultipartUpload, CompletedPart, GlacierJobParameters, ObjectLockLegalHoldStatus,
+    ObjectLockMode, RestoreRequest, ServerSideEncryption, StorageClass, Tier,
+};
+use aws_sdk_s3::output::{CreateMultipartUploadOutput, HeadObjectOutput};
+use aws_sdk_s3::types::{ByteStream, DateTime, SdkError};
 use aws_sdk_s3::{Client, Credentials, Region};
+use aws_smithy_http::body::{BoxBody, SdkBody};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use md5::{Digest, Md5};
 use tokio::io::AsyncRead;
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum RestoreStatus {
+    NotArchived,
+    NotRequested,
+    InProgress,
+    Restored(String),
+}
+
+const SSE_CUSTOMER_ALGORITHM: &str = "AES256";
+
+/// SSE-C key material in the form S3 expects it in the request headers
+struct CustomerKey {
+    key: String,
+    md5: String,
+}
+
 pub struct S3Client<'a> {
     cl: Client,
     profile: &'a Profile,
+    customer_key: Option<CustomerKey>,
 }
 
 impl<'a> S3Client<'a> {
-    pub async fn new(profile: &'a Profile) -> S3Client<'a> {
-        let creds = Credentials::new(&profile.access_key, &profile.secret_key, None, None, "sab");
+    pub async fn new(profile: &'a Profile) -> Result<S3Client<'a>> {
+        let secret_key = profile.resolve_secret_key()?;
+        let creds = Credentials::new(&profile.access_key, secret_key, None, None, "sab");
 
         let cfg = aws_config::from_env()
             .region(Region::new(profile.region.to_string()))
@@ -24,47 +57,363 @@ impl<'a> S3Client<'a> {
 
         let cl = Client::new(&cfg);
 
-        S3Client { cl, profile }
+        let customer_key = profile.resolve_sse_customer_key()?.map(|key| CustomerKey {
+            key: BASE64.encode(&key),
+            md5: BASE64.encode(Md5::digest(&key)),
+        });
+
+        Ok(S3Client {
+            cl,
+            profile,
+            customer_key,
+        })
     }
 
-    pub async fn list_uploads(&self) -> Result<Vec<String>> {
+    /// Object key of the backup with the given name under the profile prefix
+    pub fn key(&self, name: &str) -> String {
+        self.profile.prefix.to_string() + name
+    }
+
+    fn sse_customer_algorithm(&self) -> Option<String> {
+        self.customer_key
+            .as_ref()
+            .map(|_| SSE_CUSTOMER_ALGORITHM.to_string())
+    }
+
+    fn sse_customer_key(&self) -> Option<String> {
+        self.customer_key.as_ref().map(|ck| ck.key.clone())
+    }
+
+    fn sse_customer_key_md5(&self) -> Option<String> {
+        self.customer_key.as_ref().map(|ck| ck.md5.clone())
+    }
+
+    /// Returns the key and the storage class of every object under the profile prefix
+    pub async fn list_uploads(&self) -> Result<Vec<(String, String)>> {
         let resp = self
             .cl
             .list_objects_v2()
             .bucket(&self.profile.bucket)
             .prefix(&self.profile.prefix)
             .send()
-            .await?;
+            .await
+            .map_err(sdk_error("failed to list uploads"))?;
 
         let keys = resp
             .contents()
             .unwrap_or_default()
             .iter()
-            .map(|item| item.key().unwrap().to_string())
+            .map(|item| {
+                let class = item
+                    .storage_class()
+                    .map_or(DEFAULT_STORAGE_CLASS, |class| class.as_str());
+
+                (
+                    item.key().unwrap_or_default().to_string(),
+                    class.to_string(),
+                )
+            })
             .collect();
 
         Ok(keys)
     }
 
-    pub async fn create_upload(&self, name: &str, class: StorageClass) -> Result<String> {
+    pub async fn delete(&self, backup: &Backup) -> Result<()> {
+        self.delete_object(&backup.name, None).await
+    }
+
+    pub async fn abort_upload(&self, backup: &Backup) -> Result<()> {
+        self.cl
+            .abort_multipart_upload()
+            .bucket(&self.profile.bucket)
+            .key(&backup.name)
+            .upload_id(&backup.upload_id)
+            .send()
+            .await
+            .map_err(sdk_error("failed to abort upload"))?;
+
+        Ok(())
+    }
+
+    pub async fn head(&self, backup: &Backup) -> Result<HeadObjectOutput> {
+        let out = self
+            .cl
+            .head_object()
+            .bucket(&self.profile.bucket)
+            .key(&backup.name)
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
+            .send()
+            .await
+            .map_err(sdk_error("failed to get object metadata"))?;
+
+        Ok(out)
+    }
+
+    pub async fn restore(&self, backup: &Backup, days: i32, tier: Tier) -> Result<()> {
+        let params = GlacierJobParameters::builder().tier(tier).build();
+        let req = RestoreRequest::builder()
+            .days(days)
+            .glacier_job_parameters(params)
+            .build();
+
+        self.cl
+            .restore_object()
+            .bucket(&self.profile.bucket)
+            .key(&backup.name)
+            .restore_request(req)
+            .send()
+            .await
+            .map_err(sdk_error("failed to request restore"))?;
+
+        Ok(())
+    }
+
+    pub async fn restore_status(&self, backup: &Backup) -> Result<RestoreStatus> {
+        let head = self.head(backup).await?;
+
+        let archived = matches!(
+            head.storage_class(),
+            Some(StorageClass::Glacier) | Some(StorageClass::DeepArchive)
+        );
+
+        if !archived {
+            return Ok(RestoreStatus::NotArchived);
+        }
+
+        Ok(parse_restore(head.restore()))
+    }
+
+    /// Check that the bucket exists and is accessible with the profile credentials
+    pub async fn head_bucket(&self) -> Result<()> {
+        self.cl
+            .head_bucket()
+            .bucket(&self.profile.bucket)
+            .send()
+            .await
+            .map_err(sdk_error("failed to access bucket"))?;
+
+        Ok(())
+    }
+
+    pub async fn bucket_region(&self) -> Result<String> {
+        let out = self
+            .cl
+            .get_bucket_location()
+            .bucket(&self.profile.bucket)
+            .send()
+            .await
+            .map_err(sdk_error("failed to get bucket location"))?;
+
+        // Buckets in us-east-1 have no location constraint, EU is the legacy name of eu-west-1
+        let region = match out.location_constraint().map(|loc| loc.as_str()) {
+            None | Some("") => "us-east-1",
+            Some("EU") => "eu-west-1",
+            Some(region) => region,
+        };
+
+        Ok(region.to_string())
+    }
+
+    /// Upload a small object with the profile server-side encryption settings,
+    /// returning its version ID on versioned buckets
+    pub async fn put_object(&self, key: &str, body: Bytes) -> Result<Option<String>> {
+        let out = self
+            .cl
+            .put_object()
+            .bucket(&self.profile.bucket)
+            .key(key)
+            .content_md5(BASE64.encode(Md5::digest(&body)))
+            .set_server_side_encryption(self.profile.sse.as_deref().map(ServerSideEncryption::from))
+            .set_ssekms_key_id(self.profile.sse_kms_key_id.clone())
+            .set_bucket_key_enabled(self.profile.sse_bucket_key.then_some(true))
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(sdk_error("failed to put object"))?;
+
+        Ok(out.version_id().map(str::to_string))
+    }
+
+    pub async fn get_object(&self, key: &str) -> Result<Bytes> {
+        let out = self
+            .cl
+            .get_object()
+            .bucket(&self.profile.bucket)
+            .key(key)
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
+            .send()
+            .await
+            .map_err(sdk_error("failed to get object"))?;
+
+        let data = out
+            .body
+            .collect()
+            .await
+            .map_err(|e| SabError::Network(format!("failed to read object: {}", e)))?;
+
+        Ok(data.into_bytes())
+    }
+
+    /// Delete the object, or only the given version of it which leaves no delete marker behind
+    pub async fn delete_object(&self, key: &str, version_id: Option<&str>) -> Result<()> {
+        self.cl
+            .delete_object()
+            .bucket(&self.profile.bucket)
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await
+            .map_err(sdk_error("failed to delete object"))?;
+
+        Ok(())
+    }
+
+    /// Start a multipart upload, upload a part and abort it
+    pub async fn check_multipart(&self, key: &str) -> Result<()> {
+        let backup = Backup {
+            name: key.to_string(),
+            storage_class: DEFAULT_STORAGE_CLASS.to_string(),
+            ..Default::default()
+        };
+
+        let upload_id = self.create_upload(&backup).await?;
+        let backup = Backup {
+            upload_id,
+            ..backup
+        };
+
+        let res = self
+            .upload_chunk(&backup, 1, Bytes::from_static(b"sab"), Arc::new(|_| {}))
+            .await;
+
+        self.abort_upload(&backup).await?;
+
+        res.map(|_| ())
+    }
+
+    /// Object Lock default retention of the bucket, `None` when Object Lock isn't enabled
+    pub async fn object_lock(&self) -> Result<Option<String>> {
+        let res = self
+            .cl
+            .get_object_lock_configuration()
+            .bucket(&self.profile.bucket)
+            .send()
+            .await;
+
+        let out = match res {
+            Ok(out) => out,
+            Err(SdkError::ServiceError(err))
+                if err.err().code() == Some("ObjectLockConfigurationNotFoundError") =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(sdk_error("failed to get object lock configuration")(err)),
+        };
+
+        let retention = out
+            .object_lock_configuration()
+            .and_then(|cfg| cfg.rule())
+            .and_then(|rule| rule.default_retention());
+
+        let status = match retention {
+            Some(retention) => format!(
+                "enabled, default retention {} {} days {} years",
+                retention
+                    .mode()
+                    .map(|mode| mode.as_str())
+                    .unwrap_or_default(),
+                retention.days(),
+                retention.years()
+            ),
+            None => "enabled, no default retention".to_string(),
+        };
+
+        Ok(Some(status))
+    }
+
+    /// Versioning status of the bucket, `None` when it has never been enabled
+    pub async fn versioning(&self) -> Result<Option<String>> {
+        let out = self
+            .cl
+            .get_bucket_versioning()
+            .bucket(&self.profile.bucket)
+            .send()
+            .await
+            .map_err(sdk_error("failed to get bucket versioning"))?;
+
+        Ok(out.status().map(|status| status.as_str().to_string()))
+    }
+}
+
+impl Storage for S3Client<'_> {
+    async fn create_upload(&self, backup: &Backup) -> Result<String> {
+        let tagging = backup
+            .tags
+            .iter()
+            .map(|(k, v)| format!("{}={}", urlencoding::encode(k), urlencoding::encode(v)))
+            .collect::<Vec<String>>()
+            .join("&");
+
+        let metadata = backup
+            .metadata
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
         let res: CreateMultipartUploadOutput = self
             .cl
             .create_multipart_upload()
             .bucket(&self.profile.bucket)
-            .key(name)
-            .storage_class(class)
+            .key(&backup.name)
+            .storage_class(StorageClass::from(backup.storage_class.as_str()))
+            .set_tagging((!tagging.is_empty()).then_some(tagging))
+            .set_metadata(Some(metadata))
+            .set_object_lock_mode(backup.lock.mode.as_deref().map(ObjectLockMode::from))
+            .set_object_lock_retain_until_date(
+                backup
+                    .lock
+                    .retain_until()
+                    .map(|until| DateTime::from_secs(until.timestamp())),
+            )
+            .set_object_lock_legal_hold_status(
+                backup
+                    .lock
+                    .legal_hold
+                    .then_some(ObjectLockLegalHoldStatus::On),
+            )
+            .set_server_side_encryption(self.profile.sse.as_deref().map(ServerSideEncryption::from))
+            .set_ssekms_key_id(self.profile.sse_kms_key_id.clone())
+            .set_bucket_key_enabled(self.profile.sse_bucket_key.then_some(true))
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
             .send()
-            .await?;
+            .await
+            .map_err(sdk_error("failed to create upload"))?;
 
-        Ok(res.upload_id().unwrap().to_string())
+        Ok(res.upload_id().unwrap_or_default().to_string())
     }
 
-    pub async fn upload_chunk(
+    async fn upload_chunk(
         &self,
         backup: &Backup,
         part: i32,
-        body: ByteStream,
+        body: Bytes,
+        progress: ProgressFn,
     ) -> Result<String> {
+        // Object Lock requires every part to carry a Content-MD5 header
+        let content_md5 = backup
+            .lock
+            .is_enabled()
+            .then(|| BASE64.encode(Md5::digest(&body)));
+
         let res = self
             .cl
             .upload_part()
@@ -72,14 +421,19 @@ impl<'a> S3Client<'a> {
             .bucket(&self.profile.bucket)
             .upload_id(&backup.upload_id)
             .part_number(part)
-            .body(body)
+            .set_content_md5(content_md5)
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
+            .body(ProgressBody::stream(body, progress))
             .send()
-            .await?;
+            .await
+            .map_err(sdk_error("failed to upload chunk"))?;
 
-        Ok(res.e_tag().unwrap().to_string())
+        Ok(res.e_tag().unwrap_or_default().to_string())
     }
 
-    pub async fn finish_upload(&self, backup: &Backup) -> Result<String> {
+    async fn finish_upload(&self, backup: &Backup) -> Result<String> {
         let parts: Vec<CompletedPart> = backup
             .parts
             .iter()
@@ -102,21 +456,138 @@ impl<'a> S3Client<'a> {
             .key(&backup.name)
             .multipart_upload(upload)
             .upload_id(&backup.upload_id)
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
             .send()
-            .await?;
+            .await
+            .map_err(sdk_error("failed to finish upload"))?;
 
-        Ok(res.e_tag().unwrap().to_string())
+        Ok(res.e_tag().unwrap_or_default().to_string())
     }
 
-    pub async fn download(&self, backup: &Backup) -> Result<impl AsyncRead> {
+    async fn download(&self, backup: &Backup) -> Result<impl AsyncRead + Unpin + Send> {
         let out = self
             .cl
             .get_object()
             .bucket(&self.profile.bucket)
             .key(&backup.name)
+            .set_sse_customer_algorithm(self.sse_customer_algorithm())
+            .set_sse_customer_key(self.sse_customer_key())
+            .set_sse_customer_key_md5(self.sse_customer_key_md5())
             .send()
-            .await?;
+            .await
+            .map_err(sdk_error("failed to download backup"))?;
 
         Ok(out.body.into_async_read())
     }
 }
+
+// The x-amz-restore header looks like either `ongoing-request="true"` or
+// `ongoing-request="false", expiry-date="Fri, 21 Dec 2012 00:00:00 GMT"`
+fn parse_restore(header: Option<&str>) -> RestoreStatus {
+    let header = match header {
+        Some(h) => h,
+        None => return RestoreStatus::NotRequested,
+    };
+
+    if header.contains("ongoing-request=\"true\"") {
+        return RestoreStatus::InProgress;
+    }
+
+    let expiry = header
+        .split_once("expiry-date=")
+        .map(|(_, date)| date.trim_matches('"').to_string())
+        .unwrap_or_default();
+
+    RestoreStatus::Restored(expiry)
+}
+
+/// Size of the frames a chunk is split into while it's being sent
+const PROGRESS_FRAME_SIZE: usize = 64 * 1024;
+
+/// Request body reporting how much of a chunk has been handed over to the connection
+struct ProgressBody {
+    data: Bytes,
+    sent: usize,
+    progress: ProgressFn,
+}
+
+impl ProgressBody {
+    fn stream(data: Bytes, progress: ProgressFn) -> ByteStream {
+        let body = SdkBody::retryable(move || {
+            SdkBody::from_dyn(BoxBody::new(ProgressBody {
+                data: data.clone(),
+                sent: 0,
+                progress: progress.clone(),
+            }))
+        });
+
+        ByteStream::new(body)
+    }
+}
+
+impl Body for ProgressBody {
+    type Data = Bytes;
+    type Error = aws_smithy_http::body::Error;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Self::Data, Self::Error>>> {
+        if self.sent >= self.data.len() {
+            return Poll::Ready(None);
+        }
+
+        let end = (self.sent + PROGRESS_FRAME_SIZE).min(self.data.len());
+        let frame = self.data.slice(self.sent..end);
+        self.sent = end;
+
+        (self.progress)(self.sent as u64);
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.sent >= self.data.len()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact((self.data.len() - self.sent) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_not_requested() {
+        assert_eq!(parse_restore(None), RestoreStatus::NotRequested);
+    }
+
+    #[test]
+    fn restore_in_progress() {
+        assert_eq!(
+            parse_restore(Some("ongoing-request=\"true\"")),
+            RestoreStatus::InProgress
+        );
+    }
+
+    #[test]
+    fn restore_completed() {
+        let header = "ongoing-request=\"false\", expiry-date=\"Fri, 21 Dec 2012 00:00:00 GMT\"";
+
+        assert_eq!(
+            parse_restore(Some(header)),
+            RestoreStatus::Restored("Fri, 21 Dec 2012 00:00:00 GMT".to_string())
+        );
+    }
+}
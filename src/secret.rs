@@ -0,0 +1,59 @@
+//! Secrets stored in the profile either inline or as a reference to where they're kept:
+//!
+//! * `file:/path` - contents of the file
+//! * `env:VAR` - value of the environment variable
+//! * `cmd:pass show sab/key` - standard output of the shell command, e.g. a password manager
+//!
+//! References are resolved only when the secret is used.
+
+use std::fs;
+use std::process::{Command, Stdio};
+
+use crate::error::{Result, ResultExt, SabError};
+use expanduser::expanduser;
+
+/// Whether the value is a reference rather than the secret itself
+pub fn is_reference(value: &str) -> bool {
+    ["file:", "env:", "cmd:"]
+        .iter()
+        .any(|prefix| value.starts_with(prefix))
+}
+
+/// Get the secret the value refers to, an inline value is returned as is.
+///
+/// The trailing newline of files and command output is stripped.
+pub fn resolve(value: &str) -> Result<String> {
+    if let Some(path) = value.strip_prefix("file:") {
+        let path = expanduser(path).or_config("failed to expand secret file path")?;
+        let secret = fs::read_to_string(&path).or_config(&format!(
+            "failed to read secret file {}",
+            path.to_string_lossy()
+        ))?;
+
+        Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+    } else if let Some(var) = value.strip_prefix("env:") {
+        std::env::var(var).or_config(&format!("failed to read secret variable {}", var))
+    } else if let Some(cmd) = value.strip_prefix("cmd:") {
+        let out = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .or_config(&format!("failed to run secret command {}", cmd))?;
+
+        if !out.status.success() {
+            return Err(SabError::Config(format!(
+                "secret command {} failed with {}",
+                cmd, out.status
+            )));
+        }
+
+        let secret = String::from_utf8(out.stdout)
+            .or_config(&format!("secret command {} printed invalid UTF-8", cmd))?;
+
+        Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+    } else {
+        Ok(value.to_string())
+    }
+}
@@ -0,0 +1,38 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::config::Backup;
+use crate::error::Result;
+
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+
+/// Called with the number of body bytes sent so far in the current request attempt
+pub type ProgressFn = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Remote object storage holding the backups.
+///
+/// Uploads are multipart: a backup is created once, its chunks are uploaded
+/// as numbered parts and the upload is finalized when all the chunks are in.
+pub trait Storage {
+    /// Start a new multipart upload for the backup and return its id
+    fn create_upload(&self, backup: &Backup) -> impl Future<Output = Result<String>> + Send;
+
+    /// Upload a single processed chunk and return its etag
+    fn upload_chunk(
+        &self,
+        backup: &Backup,
+        part: i32,
+        body: Bytes,
+        progress: ProgressFn,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Complete the multipart upload and return the etag of the resulting object
+    fn finish_upload(&self, backup: &Backup) -> impl Future<Output = Result<String>> + Send;
+
+    /// Open a stream reading the whole processed backup object
+    fn download(
+        &self,
+        backup: &Backup,
+    ) -> impl Future<Output = Result<impl AsyncRead + Unpin + Send>> + Send;
+}
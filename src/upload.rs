@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::{Backup, ObjectLock, UploadPart, DEFAULT_STORAGE_CLASS};
+use crate::error::{Result, ResultExt, SabError};
+use crate::event::{Event, EventHandler};
+use crate::storage::{ProgressFn, Storage};
+
+use bytes::Bytes;
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use orion::aead;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// S3 doesn't allow more than 10 000 parts in a multipart upload
+pub const MAX_CHUNKS: u64 = 10_000;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 100_000_000;
+
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// Chunked, optionally compressed and encrypted, resumable upload.
+///
+/// The input is read chunk by chunk, every chunk is processed and uploaded as
+/// a separate part. When a state file is set, the backup manifest is saved
+/// after every uploaded chunk and an interrupted upload is resumed from the
+/// last uploaded chunk, provided the same input is supplied again.
+pub struct Uploader<'a, S, R> {
+    storage: &'a S,
+    reader: R,
+    name: String,
+    prefix: String,
+    chunk_size: usize,
+    compression_enabled: bool,
+    encryption_key: Option<Vec<u8>>,
+    storage_class: String,
+    tags: BTreeMap<String, String>,
+    metadata: BTreeMap<String, String>,
+    lock: ObjectLock,
+    size_hint: Option<u64>,
+    state_file: Option<PathBuf>,
+    job: Option<String>,
+    snapshot: String,
+    retries: usize,
+    on_event: Option<EventHandler>,
+}
+
+impl<'a, S: Storage, R: AsyncRead + Unpin> Uploader<'a, S, R> {
+    /// Upload `reader` into the object `name`
+    pub fn new(storage: &'a S, reader: R, name: &str) -> Self {
+        Uploader {
+            storage,
+            reader,
+            name: name.to_string(),
+            prefix: "".to_string(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            compression_enabled: false,
+            encryption_key: None,
+            storage_class: DEFAULT_STORAGE_CLASS.to_string(),
+            tags: Default::default(),
+            metadata: Default::default(),
+            lock: Default::default(),
+            size_hint: None,
+            state_file: None,
+            job: None,
+            snapshot: "".to_string(),
+            retries: DEFAULT_RETRIES,
+            on_event: None,
+        }
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression_enabled = enabled;
+        self
+    }
+
+    /// Encrypt every chunk with the given key, encryption is disabled by default
+    pub fn encryption_key(mut self, key: Option<Vec<u8>>) -> Self {
+        self.encryption_key = key;
+        self
+    }
+
+    pub fn storage_class(mut self, class: &str) -> Self {
+        self.storage_class = class.to_string();
+        self
+    }
+
+    pub fn tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: BTreeMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn lock(mut self, lock: ObjectLock) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Total input size, used to validate the number of chunks and report progress
+    pub fn size_hint(mut self, size: u64) -> Self {
+        self.size_hint = Some(size);
+        self
+    }
+
+    /// Persist the backup manifest to the given file and resume from it if it exists
+    pub fn state_file(mut self, path: PathBuf) -> Self {
+        self.state_file = Some(path);
+        self
+    }
+
+    /// Record the job the backup is made by in the manifest
+    pub fn job(mut self, job: &str) -> Self {
+        self.job = Some(job.to_string());
+        self
+    }
+
+    /// Record the snapshot ID of the backup in the manifest
+    pub fn snapshot(mut self, snapshot: &str) -> Self {
+        self.snapshot = snapshot.to_string();
+        self
+    }
+
+    /// How many times a chunk upload failing with a transient error is retried
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.on_event = Some(Arc::new(handler));
+        self
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(handler) = &self.on_event {
+            handler(&event);
+        }
+    }
+
+    // Translate the processed bytes sent for a chunk into the input bytes
+    // uploaded overall, so the progress is reported against the input size
+    fn chunk_progress(
+        &self,
+        offset: u64,
+        original_size: usize,
+        processed_size: usize,
+    ) -> ProgressFn {
+        let handler = self.on_event.clone();
+        let total = self.size_hint;
+        let ratio = original_size as f64 / processed_size.max(1) as f64;
+
+        Arc::new(move |sent| {
+            if let Some(handler) = &handler {
+                handler(&Event::Progress {
+                    bytes: offset + (sent as f64 * ratio) as u64,
+                    total,
+                });
+            }
+        })
+    }
+
+    fn save(&self, backup: &Backup) -> Result<()> {
+        match &self.state_file {
+            Some(path) => backup
+                .save(path.as_path())
+                .map_err(|e| e.context("failed to save backup config")),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the upload, returning the manifest of the completed backup
+    pub async fn upload(mut self) -> Result<Backup> {
+        let started = Instant::now();
+
+        if let Some(size) = self.size_hint {
+            let num_chunks = size / self.chunk_size as u64;
+            if num_chunks > MAX_CHUNKS {
+                return Err(SabError::Config(format!(
+                    "the total number of chunks {} exceeds the maximum amount of {}, consider increasing the chunk size",
+                    num_chunks, MAX_CHUNKS
+                )));
+            }
+        }
+
+        let existing = self.state_file.as_ref().filter(|path| path.exists());
+
+        let mut backup = match existing {
+            Some(path) => Backup::load(path.as_path())
+                .map_err(|e| e.context("failed to load backup config"))?,
+            None => {
+                let mut backup = Backup {
+                    name: self.name.clone(),
+                    prefix: self.prefix.clone(),
+                    chunk_size: self.chunk_size,
+                    upload_id: "".to_string(),
+                    parts: vec![],
+                    done: false,
+                    started: Utc::now().to_string(),
+                    completed: "".to_string(),
+                    sha256: "".to_string(),
+                    compression_enabled: self.compression_enabled,
+                    encryption_enabled: self.encryption_key.is_some(),
+                    etag: "".to_string(),
+                    storage_class: self.storage_class.clone(),
+                    tags: self.tags.clone(),
+                    metadata: self.metadata.clone(),
+                    lock: self.lock.clone(),
+                    job: self.job.clone(),
+                    snapshot: self.snapshot.clone(),
+                };
+
+                backup.upload_id = self.storage.create_upload(&backup).await?;
+                self.save(&backup)?;
+
+                backup
+            }
+        };
+
+        self.emit(Event::UploadStarted {
+            name: backup.name.clone(),
+            resumed: existing.is_some(),
+        });
+
+        let encryption_key = match (&self.encryption_key, backup.encryption_enabled) {
+            (Some(key), true) => {
+                Some(aead::SecretKey::from_slice(key).or_crypto("failed to load encryption key")?)
+            }
+            (None, true) => {
+                return Err(SabError::Crypto(
+                    "backup is encrypted but no encryption key was provided".to_string(),
+                ))
+            }
+            (_, false) => None,
+        };
+
+        let chunk_size = backup.chunk_size;
+        let existing_parts = backup.parts.len();
+        let mut idx: usize = 1;
+
+        let mut hasher = Sha256::new();
+        let mut uploaded_size: u64 = 0;
+
+        loop {
+            let mut buf = Vec::with_capacity(chunk_size);
+            let size = (&mut self.reader)
+                .take(chunk_size as u64)
+                .read_to_end(&mut buf)
+                .await
+                .or_io("failed to read from upload input")?;
+
+            if size == 0 {
+                break;
+            }
+
+            if idx as u64 > MAX_CHUNKS {
+                return Err(SabError::Config(format!(
+                    "the total number of chunks exceeds the maximum amount of {}, consider increasing the chunk size",
+                    MAX_CHUNKS
+                )));
+            }
+
+            // Update hashes
+            hasher.update(buf.as_slice());
+            uploaded_size += size as u64;
+
+            if idx <= existing_parts {
+                // A changed input must not be stitched onto the parts uploaded before
+                let sha256 = hex::encode(Sha256::digest(buf.as_slice()));
+                if sha256 != backup.parts[idx - 1].original_sha256 {
+                    return Err(SabError::Integrity(format!(
+                        "chunk {} of the input differs from the one uploaded before, \
+                         the input changed since the upload of {} was interrupted",
+                        idx, &backup.name
+                    )));
+                }
+
+                self.emit(Event::ChunkSkipped { idx });
+                self.emit(Event::Progress {
+                    bytes: uploaded_size,
+                    total: self.size_hint,
+                });
+                idx += 1;
+                continue;
+            }
+
+            let chunk_started = Instant::now();
+            let original_sha256 = hex::encode(Sha256::digest(buf.as_slice()));
+
+            if backup.compression_enabled {
+                let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+                enc.write_all(buf.as_slice())
+                    .or_io("failed to compress chunk")?;
+                buf = enc.finish().or_io("failed to complete chunk compression")?;
+            }
+
+            if let Some(key) = &encryption_key {
+                buf = aead::seal(key, buf.as_slice()).or_crypto("failed to encrypt chunk")?;
+            }
+
+            let processed_sha256 = hex::encode(Sha256::digest(buf.as_slice()));
+            let processed_size = buf.len() as u64;
+
+            let body = Bytes::from(buf);
+            let mut attempt = 0;
+
+            let etag = loop {
+                let progress = self.chunk_progress(uploaded_size - size as u64, size, body.len());
+
+                match self
+                    .storage
+                    .upload_chunk(&backup, idx as i32, body.clone(), progress)
+                    .await
+                {
+                    Ok(etag) => break etag,
+                    Err(err) if err.is_transient() && attempt < self.retries => {
+                        attempt += 1;
+
+                        self.emit(Event::Retry {
+                            idx,
+                            attempt,
+                            error: err.to_string(),
+                        });
+
+                        tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+
+            self.emit(Event::ChunkUploaded {
+                idx,
+                original_size: size as u64,
+                processed_size,
+                original_sha256: original_sha256.clone(),
+                processed_sha256: processed_sha256.clone(),
+                duration: chunk_started.elapsed(),
+                progress: self
+                    .size_hint
+                    .map(|total| uploaded_size as f64 / total as f64 * 100.),
+            });
+
+            backup.parts.push(UploadPart {
+                idx,
+                etag,
+                original_size: size as u64,
+                processed_size,
+                original_sha256,
+                processed_sha256,
+            });
+            self.save(&backup)?;
+
+            if size != chunk_size {
+                break;
+            }
+
+            idx += 1;
+        }
+
+        backup.etag = self.storage.finish_upload(&backup).await?;
+        backup.done = true;
+        backup.completed = Utc::now().to_string();
+        backup.sha256 = hex::encode(hasher.finalize());
+        self.save(&backup)?;
+
+        self.emit(Event::UploadCompleted {
+            name: backup.name.clone(),
+            size: uploaded_size,
+            sha256: backup.sha256.clone(),
+            duration: started.elapsed(),
+        });
+
+        Ok(backup)
+    }
+}